@@ -0,0 +1,477 @@
+//! Branch and bound for mid-size reduced wordlists (hundreds to a few thousand words), starting
+//! from the length of an existing portmantout, e.g. the best that `search` has found.
+//!
+//! The costs between words are exact (see `carrycoat::links`), and a dummy word stands for the
+//! two ends of the portmantout, so we are looking for the cheapest tour. Each node bounds its
+//! tours with the minimum-cost cycle cover (the assignment relaxation that `lower_bound
+//! --assignment` uses, solved with the same `carrycoat::flow::MinCostFlow`, but with exact
+//! costs). If the cover has more than one cycle, we branch on the cycle with the fewest free
+//! edges as Carpaneto and Toth do: the k-th child excludes the k-th edge of the cycle and
+//! includes the ones before it. Each child starts from its parent's cover, and only has to
+//! reroute around its excluded edge, with a single augmenting path.
+//!
+//! The incumbent only prunes once we have checked that it is a portmantout of the reduced
+//! words: it contains each of them, each two adjacent letters are in some word, and it starts
+//! with the starticle if there is one.
+//!
+//! Stops when the tree is exhausted, which proves the best portmantout optimal, or after
+//! `--time-limit SECONDS` of branching, and then reports the gap between the best portmantout and the least
+//! bound of any open node. Writes an improved portmantout to `--output FILE` (by default
+//! out/bb-LENGTH.txt).
+
+extern crate carrycoat;
+
+use carrycoat::links::{self, Dictionary, Targets};
+
+use carrycoat::flow::MinCostFlow;
+
+const INF: i64 = i64::MAX / 4;
+const NONE: usize = usize::MAX;
+
+const SOURCE: usize = 0;
+const SINK: usize = 1;
+
+/// The assignment relaxation, as a min-cost flow from rows to columns as in `lower_bound`. Its
+/// solutions are cycle covers, given as the successor of each node.
+#[derive(Clone)]
+struct Assignment {
+    flow: MinCostFlow,
+    // The edge from the source to each row, and from each column to the sink.
+    row_edges: Vec<usize>,
+    col_edges: Vec<usize>,
+    // edges[i][j] is the edge from row i to column j, or NONE if i can't go to j.
+    edges: Vec<Vec<usize>>,
+}
+
+impl Assignment {
+    fn new(costs: &[Vec<i64>]) -> Assignment {
+        let size = costs.len();
+        let mut flow = MinCostFlow::new(2 + 2 * size);
+        let row_edges = (0..size).map(|idx| flow.add_edge(SOURCE, 2 + idx, 1, 0)).collect();
+        let col_edges = (0..size).map(|idx| flow.add_edge(2 + size + idx, SINK, 1, 0)).collect();
+        let edges = costs.iter().enumerate().map(|(i, row_costs)| {
+            row_costs.iter().enumerate().map(|(j, &cost)| {
+                if cost >= INF { NONE } else { flow.add_edge(2 + i, 2 + size + j, 1, cost) }
+            }).collect()
+        }).collect();
+        Assignment { flow, row_edges, col_edges, edges }
+    }
+
+    /// The minimum-cost cycle cover with its cost, or None if there is no cycle cover.
+    fn solve(&mut self) -> Option<(i64, Vec<usize>)> {
+        let size = self.edges.len();
+        let (total_flow, cost) = self.flow.solve(SOURCE, SINK, size as i64);
+        if total_flow < size as i64 {
+            return None;
+        }
+        Some((cost, self.successor()))
+    }
+
+    fn successor(&self) -> Vec<usize> {
+        self.edges.iter().map(|row_edges| {
+            row_edges.iter().position(|&edge| edge != NONE && self.flow.flow(edge) > 0).unwrap()
+        }).collect()
+    }
+
+    /// The graph of `node`: its cycle cover as the flow, and none of the edges that it rules out.
+    fn for_node(&self, node: &Node) -> Assignment {
+        let mut assignment = self.clone();
+        for (i, &j) in node.successor.iter().enumerate() {
+            assignment.flow.set_flow(self.row_edges[i], 1);
+            assignment.flow.set_flow(self.edges[i][j], 1);
+            assignment.flow.set_flow(self.col_edges[j], 1);
+        }
+        for &(i, j) in &node.excluded {
+            assignment.forbid(i, j);
+        }
+        for &(i, j) in &node.included {
+            assignment.include(i, j);
+        }
+        assignment
+    }
+
+    fn forbid(&mut self, i: usize, j: usize) {
+        if self.edges[i][j] != NONE {
+            self.flow.remove_edge(self.edges[i][j]);
+        }
+    }
+
+    /// Forbids every other edge out of i and into j. The cover must already use (i, j).
+    fn include(&mut self, i: usize, j: usize) {
+        for other in 0..self.edges.len() {
+            if other != j {
+                self.forbid(i, other);
+            }
+            if other != i {
+                self.forbid(other, j);
+            }
+        }
+    }
+
+    /// Forbids the edge (i, j), which the cover uses, and reroutes from row i to column j along
+    /// the cheapest augmenting path. Returns what the path costs and the new cover, or None if
+    /// there is no such path.
+    fn exclude(&mut self, i: usize, j: usize) -> Option<(i64, Vec<usize>)> {
+        let size = self.edges.len();
+        self.forbid(i, j);
+        // The rest of the cover is still optimal, so one path is enough.
+        let (total_flow, cost) = self.flow.solve(2 + i, 2 + size + j, 1);
+        if total_flow < 1 {
+            return None;
+        }
+        Some((cost, self.successor()))
+    }
+}
+
+/// The cycles of a cycle cover, as lists of nodes.
+fn cycles(successor: &[usize]) -> Vec<Vec<usize>> {
+    let mut seen = vec![false; successor.len()];
+    let mut cycles = Vec::new();
+    for start in 0..successor.len() {
+        let mut cycle = Vec::new();
+        let mut current = start;
+        while !seen[current] {
+            seen[current] = true;
+            cycle.push(current);
+            current = successor[current];
+        }
+        if !cycle.is_empty() {
+            cycles.push(cycle);
+        }
+    }
+    cycles
+}
+
+/// Checks that `portmantout` contains every reduced word, and that each two adjacent letters
+/// are in some occurrence of a word or reduced word.
+fn check_portmantout(portmantout: &[u8], reduced: &[Vec<u8>], words: &[Vec<u8>]) -> Result<(), String> {
+    use std::collections::HashSet;
+
+    let reduced_set: HashSet<&[u8]> = reduced.iter().map(|w| &w[..]).collect();
+    let word_set: HashSet<&[u8]> = words.iter().chain(reduced).map(|w| &w[..]).collect();
+    let longest = word_set.iter().map(|w| w.len()).max().unwrap_or(0);
+    let mut found = HashSet::new();
+    // The end of the furthest-reaching word that starts at or before `start`.
+    let mut covered_to = 0;
+    for start in 0..portmantout.len() {
+        for end in (start + 1)..(::std::cmp::min(portmantout.len(), start + longest) + 1) {
+            let candidate = &portmantout[start..end];
+            if reduced_set.contains(candidate) {
+                found.insert(candidate);
+            }
+            if end > covered_to && word_set.contains(candidate) {
+                covered_to = end;
+            }
+        }
+        if start + 1 < portmantout.len() && covered_to < start + 2 {
+            return Err(format!("no word covers letters {} and {}", start, start + 1));
+        }
+    }
+    match reduced.iter().find(|w| !found.contains(&w[..])) {
+        Some(word) => Err(format!("{:?} is missing", ::std::str::from_utf8(word))),
+        None => Ok(()),
+    }
+}
+
+#[test]
+fn test_check_portmantout() {
+    let reduced = vec![b"cat".to_vec(), b"tar".to_vec()];
+    let words = vec![b"at".to_vec()];
+    assert_eq!(check_portmantout(b"catar", &reduced, &words), Ok(()));
+    assert!(check_portmantout(b"cat", &reduced, &words).is_err());
+    assert_eq!(check_portmantout(b"cattar", &reduced, &words),
+               Err("no word covers letters 2 and 3".to_string()));
+}
+
+/// A subproblem: the tours that use every included edge and no excluded one.
+struct Node {
+    bound: i64,
+    successor: Vec<usize>,
+    included: Vec<(usize, usize)>,
+    excluded: Vec<(usize, usize)>,
+}
+
+struct Outcome {
+    best: Option<(i64, Vec<usize>)>,
+    lower_bound: i64,
+    nodes: usize,
+    optimal: bool,
+}
+
+/// The cheapest tour through all nodes, if it costs less than `incumbent`. `costs[i][j]` is
+/// the cost of going from i to j, or INF.
+fn branch_and_bound(costs: &[Vec<i64>], incumbent: i64, deadline: Option<::std::time::Instant>) -> Outcome {
+    let size = costs.len();
+    let mut outcome = Outcome { best: None, lower_bound: incumbent, nodes: 0, optimal: false };
+    let mut best_cost = incumbent;
+
+    let assignment = Assignment::new(costs);
+    let (bound, successor) = match assignment.clone().solve() {
+        Some(root) => root,
+        None => {
+            outcome.optimal = true;
+            return outcome;
+        }
+    };
+    let mut stack = vec![Node {
+        bound,
+        successor,
+        included: Vec::new(),
+        excluded: Vec::new(),
+    }];
+    println!("root bound: {}", stack[0].bound);
+
+    while let Some(node) = stack.pop() {
+        if node.bound >= best_cost {
+            continue;
+        }
+        if deadline.is_some_and(|deadline| ::std::time::Instant::now() >= deadline) {
+            stack.push(node);
+            break;
+        }
+        outcome.nodes += 1;
+        if outcome.nodes.is_multiple_of(1000) {
+            let open_bound = stack.iter().map(|n| n.bound).chain(Some(node.bound)).min().unwrap();
+            println!("nodes: {}, open: {}, lower bound: {}, best: {}", outcome.nodes, stack.len() + 1,
+                     open_bound, best_cost);
+        }
+
+        let cycles = cycles(&node.successor);
+        if cycles.len() == 1 {
+            best_cost = node.bound;
+            let mut order = vec![0];
+            while order.len() < size {
+                order.push(node.successor[*order.last().unwrap()]);
+            }
+            println!("found a tour of cost {}", best_cost);
+            outcome.best = Some((best_cost, order));
+            continue;
+        }
+
+        let mut row_fixed = vec![false; size];
+        for &(i, _) in &node.included {
+            row_fixed[i] = true;
+        }
+        let free_edges = |cycle: &Vec<usize>| -> Vec<(usize, usize)> {
+            cycle.iter().map(|&i| (i, node.successor[i])).filter(|&(i, _)| !row_fixed[i]).collect()
+        };
+        let edges = match cycles.iter().map(&free_edges).filter(|edges| !edges.is_empty())
+            .min_by_key(|edges| edges.len()) {
+            Some(edges) => edges,
+            None => continue,
+        };
+
+        let mut children = Vec::new();
+        let mut included = node.included.clone();
+        let mut graph = assignment.for_node(&node);
+        for &(i, j) in &edges {
+            if let Some((path_cost, successor)) = graph.clone().exclude(i, j) {
+                let bound = node.bound - costs[i][j] + path_cost;
+                if bound < best_cost {
+                    let mut excluded = node.excluded.clone();
+                    excluded.push((i, j));
+                    children.push(Node { bound, successor, included: included.clone(), excluded });
+                }
+            }
+            // The later children keep this edge.
+            included.push((i, j));
+            graph.include(i, j);
+        }
+        // Most promising child last, so that we dive into it first.
+        children.sort_by_key(|child| ::std::cmp::Reverse(child.bound));
+        stack.extend(children);
+    }
+
+    stack.retain(|node| node.bound < best_cost);
+    outcome.optimal = stack.is_empty();
+    outcome.lower_bound = stack.iter().map(|node| node.bound).min().map_or(best_cost, |b| ::std::cmp::min(b, best_cost));
+    outcome
+}
+
+#[test]
+fn test_branch_and_bound() {
+    // Seven nodes with pseudo-random costs, some of them missing; compare against every tour.
+    let size = 7;
+    fn permutations(rest: &mut Vec<usize>, prefix: &mut Vec<usize>, out: &mut Vec<Vec<usize>>) {
+        if rest.is_empty() {
+            out.push(prefix.clone());
+        }
+        for idx in 0..rest.len() {
+            let node = rest.remove(idx);
+            prefix.push(node);
+            permutations(rest, prefix, out);
+            prefix.pop();
+            rest.insert(idx, node);
+        }
+    }
+    let mut tours = Vec::new();
+    permutations(&mut (1..size).collect(), &mut vec![0], &mut tours);
+
+    let mut state: u64 = 1;
+    for _ in 0..50 {
+        let costs: Vec<Vec<i64>> = (0..size).map(|i| (0..size).map(|j| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            let cost = (state >> 33) % 13;
+            if i == j || cost == 12 { INF } else { cost as i64 - 4 }
+        }).collect()).collect();
+        let tour_cost = |tour: &[usize]| -> i64 {
+            (0..size).map(|k| costs[tour[k]][tour[(k + 1) % size]]).fold(0, |total, cost| {
+                if total >= INF || cost >= INF { INF } else { total + cost }
+            })
+        };
+        let brute_force = tours.iter().map(|tour| tour_cost(tour)).min().unwrap();
+
+        let outcome = branch_and_bound(&costs, INF, None);
+        assert!(outcome.optimal);
+        match outcome.best {
+            Some((cost, order)) => {
+                assert_eq!(cost, brute_force);
+                assert_eq!(tour_cost(&order), brute_force);
+            }
+            None => assert_eq!(brute_force, INF),
+        }
+    }
+}
+
+fn main_result() -> ::std::result::Result<(), Box<dyn (::std::error::Error)>> {
+    use std::io::{BufRead, Read, Write};
+
+    let mut time_limit = None;
+    let mut output = None;
+    let mut args = Vec::new();
+    let mut arg_iter = ::std::env::args();
+    while let Some(arg) = arg_iter.next() {
+        match &arg[..] {
+            "--time-limit" => {
+                let seconds = arg_iter.next().ok_or("--time-limit requires SECONDS")?.parse()?;
+                time_limit = Some(::std::time::Duration::from_secs(seconds));
+            }
+            "--output" => {
+                output = Some(arg_iter.next().ok_or("--output requires FILE")?);
+            }
+            _ => args.push(arg),
+        }
+    }
+    if args.len() < 3 || args.len() > 4 {
+        println!("usage: {} [--time-limit SECONDS] [--output FILE] \
+                  REDUCED_WORDLIST_FILE WORDLIST_FILE [PORTMANTOUT_FILE]", args[0]);
+        return Ok(());
+    }
+    let started = ::std::time::Instant::now();
+
+    let mut reduced = Vec::new();
+    for maybe_word in ::std::io::BufReader::new(::std::fs::File::open(&args[1])?).split(b'\n') {
+        let word = maybe_word?;
+        if !word.is_empty() {
+            reduced.push(word);
+        }
+    }
+    let mut words = Vec::new();
+    for maybe_word in ::std::io::BufReader::new(::std::fs::File::open(&args[2])?).split(b'\n') {
+        let word = maybe_word?;
+        if !word.is_empty() {
+            words.push(word);
+        }
+    }
+    // The costs are only exact if no word reaches across a whole reduced word.
+    for r in &reduced {
+        if let Some(word) = words.iter().find(|w| *w != r && carrycoat::contains_subsequence(w, r)) {
+            return Err(format!("{:?} is contained in {:?}; reduce the wordlist first",
+                               ::std::str::from_utf8(r), ::std::str::from_utf8(word)).into());
+        }
+    }
+    let total_len: usize = reduced.iter().map(|w| w.len()).sum();
+    // Node 0 is the dummy, and word i is node i + 1. Like `search`, we start with the
+    // starticle if there is one.
+    let starticle = reduced.iter().position(|w| w.starts_with(b"portmanteau"));
+
+    let incumbent = if args.len() == 4 {
+        let mut portmantout = Vec::new();
+        ::std::fs::File::open(&args[3])?.read_to_end(&mut portmantout)?;
+        while portmantout.last().is_some_and(|&c| (c as char).is_whitespace()) {
+            portmantout.pop();
+        }
+        check_portmantout(&portmantout, &reduced, &words)
+            .map_err(|e| format!("{} is not a portmantout of these words: {}", args[3], e))?;
+        // Otherwise it may be shorter than anything that we search through.
+        if let Some(starticle) = starticle {
+            if !portmantout.starts_with(&reduced[starticle]) {
+                return Err(format!("{} does not start with {:?}", args[3],
+                                   ::std::str::from_utf8(&reduced[starticle])).into());
+            }
+        }
+        println!("incumbent length: {}", portmantout.len());
+        Some(portmantout.len())
+    } else {
+        None
+    };
+
+    let dictionary = Dictionary::new(&words);
+    let targets = Targets::new(&reduced);
+    let size = reduced.len() + 1;
+    let mut costs = vec![vec![INF; size]; size];
+    for (from_idx, row) in costs.iter_mut().enumerate().skip(1) {
+        row[0] = 0;
+        for (to_idx, link) in links::links_from(from_idx - 1, &reduced, &targets, &dictionary).into_iter().enumerate() {
+            if let Some(link) = link {
+                row[to_idx + 1] = link.cost();
+            }
+        }
+    }
+    for (to_idx, cost) in costs[0].iter_mut().enumerate().skip(1) {
+        if starticle.is_none_or(|s| s + 1 == to_idx) {
+            *cost = 0;
+        }
+    }
+    println!("words: {}, total characters: {}, link costs took {:.1}s",
+             reduced.len(), total_len, started.elapsed().as_secs_f64());
+
+    let incumbent_cost = incumbent.map_or(INF, |len| len as i64 - total_len as i64);
+    let deadline = time_limit.map(|limit| ::std::time::Instant::now() + limit);
+    let outcome = branch_and_bound(&costs, incumbent_cost, deadline);
+
+    println!("nodes: {}", outcome.nodes);
+    let lower_bound = total_len as i64 + outcome.lower_bound;
+    let best_len = match outcome.best {
+        Some((cost, ref order)) => {
+            let order: Vec<usize> = order[1..].iter().map(|node| node - 1).collect();
+            let portmantout = links::assemble(&reduced, &order, |from, to| {
+                links::link(&reduced[from], &reduced[to], &dictionary).unwrap()
+            });
+            assert_eq!(portmantout.len() as i64, total_len as i64 + cost);
+            let output = output.unwrap_or_else(|| format!("out/bb-{}.txt", portmantout.len()));
+            let mut file = ::std::fs::File::create(&output)?;
+            file.write_all(&portmantout)?;
+            file.write_all(b"\n")?;
+            println!("wrote {}", output);
+            Some(portmantout.len() as i64)
+        }
+        None => {
+            if incumbent.is_some() {
+                println!("found nothing shorter than the incumbent");
+            }
+            incumbent.map(|len| len as i64)
+        }
+    };
+    match best_len {
+        Some(best_len) if outcome.optimal => println!("best length: {} (optimal)", best_len),
+        Some(best_len) => {
+            println!("best length: {}", best_len);
+            println!("lower bound: {}", lower_bound);
+            println!("gap: {} ({:.3}%)", best_len - lower_bound,
+                     100.0 * (best_len - lower_bound) as f64 / best_len as f64);
+        }
+        None if outcome.optimal => println!("there is no portmantout of these words"),
+        None => println!("no portmantout found; lower bound: {}", lower_bound),
+    }
+    Ok(())
+}
+
+pub fn main() {
+    match main_result() {
+        Ok(()) => {}
+        Err(e) => {
+            println!("error: {}", e);
+        }
+    }
+}
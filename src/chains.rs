@@ -0,0 +1,549 @@
+//! Chains of particles, and the local search over them that `search` runs: `break_chains`
+//! cuts a few random edges, `coalesce` joins the chains back up again, and
+//! `State::crossover` combines two solutions. An edge is always an overlap or some padding,
+//! but which edges exist between which particles is up to an `Edges` implementation, and
+//! what they cost is up to a `CostModel`.
+
+use std::collections::HashSet;
+
+use cost::CostModel;
+
+#[derive(PartialEq, Eq, Debug)]
+pub struct BytesTrieKey(pub Vec<u8>);
+
+impl ::radix_trie::TrieKey for BytesTrieKey {
+    fn encode(&self) -> Vec<u8> {
+        self.0.clone()
+    }
+}
+
+pub type Trie = ::radix_trie::Trie<BytesTrieKey, ()>;
+pub type ParticleTrie = ::radix_trie::Trie<BytesTrieKey, usize>;
+
+#[derive(Clone)]
+pub enum Edge {
+    Overlapped(usize), // overlapped with at least 1 letter.
+    Padded { padding: Vec<u8> }, // includes the zero padding case.
+}
+
+impl Edge {
+    /// What the edge costs between `from` and `to`.
+    pub fn cost(&self, model: &dyn CostModel, from: &[u8], to: &[u8]) -> i64 {
+        match *self {
+            Edge::Overlapped(n) => model.overlap(n),
+            Edge::Padded { ref padding } => model.padding(from, padding, to),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Next {
+    pub next_idx: usize,
+    pub edge: Edge,
+}
+
+#[derive(Clone, Debug)]
+pub struct NoNext {
+    pub chain_start_idx: usize,
+}
+
+#[derive(Clone)]
+pub struct Prev {
+    pub prev_idx: usize,
+}
+
+#[derive(Clone)]
+pub struct NoPrev {
+    pub chain_end_idx: usize,
+}
+
+#[derive(Clone)]
+pub struct Particle {
+    pub chars: Vec<u8>,
+    pub next: Result<Next, NoNext>,
+    pub prev: Result<Prev, NoPrev>,
+}
+
+impl Particle {
+    pub fn new(chars: Vec<u8>, idx: usize) -> Particle {
+        Particle {
+            chars,
+            next: Err(NoNext {chain_start_idx: idx}),
+            prev: Err(NoPrev {chain_end_idx: idx}),
+        }
+    }
+}
+
+/// Finds the edges that `coalesce` joins chains with.
+pub trait Edges {
+    /// The best edge from `particle` to any of the particles in `starts`, which is not empty,
+    /// the cheapest under `model` where there is a choice.
+    fn find_next(&self, particle: &Particle, starts: &ParticleTrie, model: &dyn CostModel) -> Next;
+
+    /// The best edge from `from` to the specific particle `to`, if there is one.
+    fn find_edge(&self, from: &Particle, to: &Particle) -> Option<Edge>;
+}
+
+#[derive(Clone)]
+pub struct State {
+    pub particles: Vec<Particle>,
+
+    // The total length of the particles, plus the costs of the edges between them.
+    pub score: i64,
+
+    // Set of indices of base particles unconnected on the right.
+    pub unconnected_on_right: Vec<usize>,
+
+   // Set of indices of base particles unconnected on the left.
+    pub unconnected_on_left: HashSet<usize>,
+
+    pub starticle_idx: usize,
+
+    // In cyclic mode there is no real starticle. `starticle_idx` then just anchors the
+    // single chain, and `closing` is the edge from the end of that chain back to its start.
+    pub cyclic: bool,
+    pub closing: Option<Edge>,
+}
+
+impl State {
+    pub fn new(cyclic: bool) -> State {
+        State {
+            particles: Vec::new(),
+            score: 0,
+            unconnected_on_right: Vec::new(),
+            unconnected_on_left: HashSet::new(),
+            starticle_idx: 0,
+            cyclic,
+            closing: None,
+        }
+    }
+
+    /// Total characters of the particles that are still unconnected singletons.
+    pub fn unconnected_score(&self) -> i64 {
+        self.unconnected_on_left.iter().map(|&idx| self.particles[idx].chars.len() as i64).sum()
+    }
+
+    pub fn add_starticle(&mut self, particle: Vec<u8>) {
+        let idx = self.particles.len();
+        let particle = Particle::new(particle, idx);
+        self.score += particle.chars.len() as i64;
+        self.particles.push(particle);
+        self.unconnected_on_right.push(idx);
+        self.starticle_idx = idx;
+    }
+
+    pub fn add_particle(&mut self, particle: Vec<u8>) {
+        let idx = self.particles.len();
+        let particle = Particle::new(particle, idx);
+        self.score += particle.chars.len() as i64;
+        self.particles.push(particle);
+        self.unconnected_on_right.push(idx);
+        self.unconnected_on_left.insert(idx);
+    }
+
+    pub fn sanity_check(&self) {
+        assert_eq!(self.unconnected_on_right.len(), self.unconnected_on_left.len() + 1);
+    }
+
+    /// In cyclic mode, connects the end of the single remaining chain back to its start. Fails
+    /// if `edges` has no edge from the end to the start.
+    pub fn close_cycle<E: Edges>(&mut self, edges: &E, model: &dyn CostModel) -> Result<(), String> {
+        assert!(self.unconnected_on_left.is_empty());
+        assert_eq!(self.unconnected_on_right.len(), 1);
+        let end_idx = self.unconnected_on_right[0];
+        let (end, start) = (&self.particles[end_idx], &self.particles[self.starticle_idx]);
+        let edge = edges.find_edge(end, start).ok_or_else(|| {
+            format!("no way to close the cycle from {:?} to {:?}",
+                    String::from_utf8_lossy(&end.chars), String::from_utf8_lossy(&start.chars))
+        })?;
+        self.score += edge.cost(model, &end.chars, &start.chars);
+        self.closing = Some(edge);
+        Ok(())
+    }
+
+    /// Spells out the single chain that starts at the starticle, and says where in it each
+    /// particle starts.
+    pub fn assemble(&self) -> (Vec<u8>, Vec<usize>) {
+        assert!(self.unconnected_on_left.is_empty());
+        assert!(self.unconnected_on_right.len() == 1);
+        let mut chars = Vec::new();
+        let mut positions = vec![0; self.particles.len()];
+        let mut current_idx = self.starticle_idx;
+        let mut counter = 0;
+        loop {
+            counter += 1;
+            if counter > self.particles.len() {
+                panic!("loopy!");
+            }
+            let particle = &self.particles[current_idx];
+            positions[current_idx] = chars.len();
+
+            match particle.next {
+                Ok(ref next) => {
+                    current_idx = next.next_idx;
+                    match next.edge {
+                        Edge::Padded { ref padding, .. } => {
+                            chars.extend_from_slice(&particle.chars);
+                            chars.extend_from_slice(padding);
+                        }
+                        Edge::Overlapped(n) => {
+                            assert!(particle.chars.len() >= n);
+                            let write_len = particle.chars.len() - n;
+                            chars.extend_from_slice(&particle.chars[.. write_len]);
+                        }
+                    }
+                }
+                Err(_) => {
+                    match self.closing {
+                        Some(Edge::Overlapped(n)) => {
+                            // The overlapped characters are already at the start of the text.
+                            chars.extend_from_slice(&particle.chars[.. particle.chars.len() - n]);
+                        }
+                        Some(Edge::Padded { ref padding }) => {
+                            chars.extend_from_slice(&particle.chars);
+                            chars.extend_from_slice(padding);
+                        }
+                        None => {
+                            chars.extend_from_slice(&particle.chars);
+                        }
+                    }
+                    break;
+                }
+            }
+        }
+        (chars, positions)
+    }
+
+    /// Edge-assembly crossover: a child that keeps only the edges that `a` and `b` share.
+    /// The child is left as a set of chains, ready to be joined up again by `coalesce`.
+    pub fn crossover(a: &State, b: &State, model: &dyn CostModel) -> State {
+        let mut child = State::new(a.cyclic);
+        child.starticle_idx = a.starticle_idx;
+        for (idx, particle) in a.particles.iter().enumerate() {
+            child.score += particle.chars.len() as i64;
+            child.particles.push(Particle::new(particle.chars.clone(), idx));
+        }
+
+        for (idx, particle) in a.particles.iter().enumerate() {
+            if let (Ok(next), Ok(other_next)) = (particle.next.as_ref(), b.particles[idx].next.as_ref()) {
+                if next.next_idx == other_next.next_idx {
+                    child.score += next.edge.cost(model, &particle.chars, &a.particles[next.next_idx].chars);
+                    child.particles[idx].next = Ok(next.clone());
+                    child.particles[next.next_idx].prev = Ok(Prev { prev_idx: idx });
+                }
+            }
+        }
+
+        // Now walk each of the chains to fix up the links between their starts and ends.
+        for start_idx in 0..child.particles.len() {
+            if child.particles[start_idx].prev.is_ok() {
+                continue;
+            }
+            let mut end_idx = start_idx;
+            while let Ok(ref next) = child.particles[end_idx].next {
+                end_idx = next.next_idx;
+            }
+            child.particles[start_idx].prev = Err(NoPrev { chain_end_idx: end_idx });
+            child.particles[end_idx].next = Err(NoNext { chain_start_idx: start_idx });
+            child.unconnected_on_right.push(end_idx);
+            if start_idx != child.starticle_idx {
+                child.unconnected_on_left.insert(start_idx);
+            }
+        }
+        child
+    }
+}
+
+pub struct EdgeCounts {
+    pub overlapped: usize,
+    pub padded: usize,
+    pub padding_chars: usize,
+}
+
+impl State {
+    pub fn edge_counts(&self) -> EdgeCounts {
+        let mut counts = EdgeCounts { overlapped: 0, padded: 0, padding_chars: 0 };
+        let edges = self.particles.iter().filter_map(|p| p.next.as_ref().ok().map(|n| &n.edge));
+        for edge in edges.chain(self.closing.iter()) {
+            match *edge {
+                Edge::Overlapped(_) => counts.overlapped += 1,
+                Edge::Padded { ref padding } => {
+                    counts.padded += 1;
+                    counts.padding_chars += padding.len();
+                }
+            }
+        }
+        counts
+    }
+}
+
+pub fn break_chains<R>(state: &mut State, model: &dyn CostModel, rng: &mut R) where R: ::rand::Rng {
+    if let Some(edge) = state.closing.take() {
+        let end_idx = state.unconnected_on_right[0];
+        state.score -= edge.cost(model, &state.particles[end_idx].chars, &state.particles[state.starticle_idx].chars);
+    }
+
+    for particle_idx in 0..state.particles.len() {
+        state.sanity_check();
+        let maybe_next_idx = {
+            let particle = &state.particles[particle_idx];
+            if let Ok(ref next) = particle.next {
+                if rng.gen_range(0, 10000) < 3 {
+                    // We're going to break this up.
+                    state.score -= next.edge.cost(model, &particle.chars, &state.particles[next.next_idx].chars);
+                    Some(next.next_idx)
+                } else {
+                    None
+                }
+            } else {
+                None
+            }
+        };
+
+
+        if let Some(next_idx) = maybe_next_idx {
+
+            state.unconnected_on_right.push(particle_idx);
+            state.unconnected_on_left.insert(next_idx);
+
+            // need to figure out the start and the end of the chain.
+            // so walk forward to the end.
+            let (chain_start_idx, chain_end_idx) = {
+                let mut current_idx = next_idx;
+                let chain_start_idx;
+                loop {
+                    let particle = &state.particles[current_idx];
+                    match particle.next {
+                        Ok(ref next) => {
+                            current_idx = next.next_idx;
+                        }
+                        Err(ref no_next) => {
+                            chain_start_idx = no_next.chain_start_idx;
+                            break;
+                        }
+                    }
+                }
+                (chain_start_idx, current_idx)
+            };
+
+            {
+                let chain_start = &mut state.particles[chain_start_idx];
+                match chain_start.prev {
+                    Ok(_) => unreachable!(),
+                    Err(ref mut no_prev) => {
+                        no_prev.chain_end_idx = particle_idx;
+                    }
+                }
+            }
+
+            {
+                let chain_end = &mut state.particles[chain_end_idx];
+                match chain_end.next {
+                    Ok(_) => unreachable!(),
+                    Err(ref mut no_next) => {
+                        no_next.chain_start_idx = next_idx;
+                    }
+                }
+            }
+
+
+            state.particles[next_idx].prev = Err(NoPrev { chain_end_idx });
+            state.particles[particle_idx].next = Err(NoNext { chain_start_idx });
+
+        }
+    }
+}
+
+pub fn coalesce<E, R>(state: &mut State, edges: &E, model: &dyn CostModel, rng: &mut R)
+    where E: Edges, R: ::rand::Rng
+{
+    // first, form a trie containing all of the current chains of particles
+    // then, while the length of unconnected_on_right is greater than 1:
+    // pick a random idx in unconnected on right
+    // connect that particle to one of the existing chains (but not itself!)
+    //  (so temporarily remove self from the particle-chain trie?)
+    //
+    //
+
+    // With `model.overlaps_first()`, chain ends that could only be padded are `deferred` until
+    // every other chain end is, so that they don't take a particle that another chain end could
+    // have overlapped.
+    let mut deferred = HashSet::new();
+    let mut padding_allowed = !model.overlaps_first();
+
+    let mut particles_trie = ParticleTrie::new();
+    for &idx in &state.unconnected_on_left {
+        let particle = &state.particles[idx];
+        particles_trie.insert(BytesTrieKey(particle.chars.clone()), idx);
+    }
+    while !particles_trie.is_empty() {
+
+        state.sanity_check();
+
+        if deferred.len() == state.unconnected_on_right.len() {
+            padding_allowed = true;
+        }
+        let idx = loop {
+            let idx = rng.gen_range(0, state.unconnected_on_right.len());
+            if padding_allowed || !deferred.contains(&state.unconnected_on_right[idx]) {
+                break idx;
+            }
+        };
+        let particle_idx = state.unconnected_on_right.swap_remove(idx);
+
+        let chain_start_particle_idx = match state.particles[particle_idx].next {
+            Ok(_) => unreachable!(),
+            Err(ref no_next) => no_next.chain_start_idx,
+        };
+
+        // Special case when we are almost done. We need to choose the starticle chain.
+        if particles_trie.len() == 1 && chain_start_particle_idx != state.starticle_idx {
+            state.unconnected_on_right.push(particle_idx);
+            deferred.insert(particle_idx);
+            continue;
+        }
+
+
+        let best_next = {
+            let particle = &state.particles[particle_idx];
+            let chain_start_particle = &state.particles[chain_start_particle_idx];
+            let chain_start_particle_trie_key = BytesTrieKey(chain_start_particle.chars.clone());
+            // temporarily remove chain_start_particle from particles_trie, to avoid forming a cycle.
+            if chain_start_particle_idx != state.starticle_idx {
+                particles_trie.remove(&chain_start_particle_trie_key);
+            }
+
+            let best_next = edges.find_next(particle, &particles_trie, model);
+
+            if chain_start_particle_idx != state.starticle_idx {
+                particles_trie.insert(chain_start_particle_trie_key, chain_start_particle_idx);
+            }
+
+            best_next
+        };
+
+        if !padding_allowed {
+            if let Edge::Padded { .. } = best_next.edge {
+                state.unconnected_on_right.push(particle_idx);
+                deferred.insert(particle_idx);
+                continue;
+            }
+        }
+
+        let next_particle_idx = best_next.next_idx;
+        state.score += best_next.edge.cost(model, &state.particles[particle_idx].chars,
+                                           &state.particles[next_particle_idx].chars);
+        assert!(state.unconnected_on_left.remove(&next_particle_idx));
+
+        {
+            let particle = &mut state.particles[particle_idx];
+            particle.next = Ok(best_next);
+        }
+
+        let chain_end_particle_idx = {
+            let next_particle = &mut state.particles[next_particle_idx];
+            let chain_end_particle_idx = match next_particle.prev {
+                Ok(_) => unreachable!(),
+                Err(ref no_prev) => {
+                    no_prev.chain_end_idx
+                }
+            };
+            next_particle.prev = Ok(Prev { prev_idx: particle_idx, });
+            chain_end_particle_idx
+        };
+
+        // Now, update the chain_end_idx at the chain start,
+
+        {
+            let chain_start = &mut state.particles[chain_start_particle_idx];
+            match chain_start.prev {
+                Ok(_) => unreachable!(),
+                Err(ref mut no_prev) => {
+                    no_prev.chain_end_idx = chain_end_particle_idx;
+                }
+            }
+        }
+
+        // and update the chain_start_idx at the chain end.
+        {
+            let chain_end = &mut state.particles[chain_end_particle_idx];
+            match chain_end.next {
+                Ok(_) => unreachable!(),
+                Err(ref mut no_next) => {
+                    no_next.chain_start_idx = chain_start_particle_idx;
+                }
+            }
+        }
+
+        {
+            let next_particle = &state.particles[next_particle_idx];
+            particles_trie.remove(&BytesTrieKey(next_particle.chars.clone()));
+        }
+    }
+}
+
+#[test]
+fn test_crossover() {
+    use cost::Length;
+
+    /// Overlaps the particles as much as they allow, and pads with nothing otherwise.
+    struct Overlaps;
+
+    impl Edges for Overlaps {
+        fn find_next(&self, particle: &Particle, starts: &ParticleTrie, _model: &dyn CostModel) -> Next {
+            let (next_idx, overlap) = starts.iter()
+                .map(|(key, &idx)| (idx, overlap(&particle.chars, &key.0)))
+                .max_by_key(|&(_, overlap)| overlap)
+                .unwrap();
+            Next { next_idx, edge: edge(overlap) }
+        }
+
+        fn find_edge(&self, from: &Particle, to: &Particle) -> Option<Edge> {
+            Some(edge(overlap(&from.chars, &to.chars)))
+        }
+    }
+
+    fn overlap(from: &[u8], to: &[u8]) -> usize {
+        (1..::std::cmp::min(from.len(), to.len())).rev().find(|&n| from.ends_with(&to[..n])).unwrap_or(0)
+    }
+
+    fn edge(overlap: usize) -> Edge {
+        if overlap == 0 { Edge::Padded { padding: Vec::new() } } else { Edge::Overlapped(overlap) }
+    }
+
+    // A single chain through the particles in `order`, which starts with the starticle.
+    fn chain(order: &[usize]) -> State {
+        let mut state = State::new(false);
+        state.add_starticle(Vec::new());
+        for chars in &[&b"abc"[..], b"bcd", b"cde", b"xyz"] {
+            state.add_particle(chars.to_vec());
+        }
+        for pair in order.windows(2) {
+            let edge = Overlaps.find_edge(&state.particles[pair[0]], &state.particles[pair[1]]).unwrap();
+            state.score += edge.cost(&Length, &state.particles[pair[0]].chars, &state.particles[pair[1]].chars);
+            state.particles[pair[0]].next = Ok(Next { next_idx: pair[1], edge });
+            state.particles[pair[1]].prev = Ok(Prev { prev_idx: pair[0] });
+        }
+        let end_idx = *order.last().unwrap();
+        state.particles[end_idx].next = Err(NoNext { chain_start_idx: order[0] });
+        state.particles[order[0]].prev = Err(NoPrev { chain_end_idx: end_idx });
+        state.unconnected_on_right = vec![end_idx];
+        state.unconnected_on_left.clear();
+        state
+    }
+
+    let a = chain(&[0, 1, 2, 3, 4]);
+    let b = chain(&[0, 4, 1, 2, 3]);
+    assert_eq!(a.assemble().0, b"abcdexyz");
+    assert_eq!(b.assemble().0, b"xyzabcde");
+
+    let mut child = State::crossover(&a, &b, &Length);
+    let next_idx = |idx: usize| child.particles[idx].next.as_ref().ok().map(|next| next.next_idx);
+    assert_eq!((0..5).map(&next_idx).collect::<Vec<_>>(), vec![None, Some(2), Some(3), None, None]);
+    assert_eq!(child.unconnected_on_right.len(), child.unconnected_on_left.len() + 1);
+    assert_eq!(child.score, 3 + 3 + 3 + 3 - 2 - 2);
+
+    let mut rng: ::rand::XorShiftRng = ::rand::SeedableRng::from_seed([1, 2, 3, 4]);
+    coalesce(&mut child, &Overlaps, &Length, &mut rng);
+    assert_eq!(child.score, child.assemble().0.len() as i64);
+}
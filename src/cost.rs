@@ -0,0 +1,123 @@
+//! What it costs to put one particle after another. By default (`Length`) that is how many
+//! letters the join adds to the portmantout: minus the overlap, or plus the padding, so the
+//! cheapest portmantout is the shortest. `search`, `join` and `lower_bound` take any
+//! `CostModel` (`--cost`), to optimize something else instead.
+//!
+//! The cost of a portmantout is the total length of its particles plus the costs of its joins.
+//! Costs are i64 rather than isize, since `PaddedEdges` needs more than 32 bits.
+
+pub trait CostModel {
+    /// The cost of overlapping the end of one particle with the start of the next by
+    /// `overlap` letters.
+    fn overlap(&self, overlap: usize) -> i64;
+
+    /// The cost of `padding` between `from` and `to`. A model can look at the particles to
+    /// see which words the padding comes from.
+    fn padding(&self, from: &[u8], padding: &[u8], to: &[u8]) -> i64;
+
+    /// The least that any padding of `len` letters can cost. It must not decrease as `len`
+    /// grows, since lower bounds only know how short the padding could be.
+    fn min_padding(&self, len: usize) -> i64;
+
+    /// Whether `coalesce` should make every overlap it can before it pads anything, rather than
+    /// joining chains in random order. Worth it when any padding costs far more than any
+    /// overlap saves.
+    fn overlaps_first(&self) -> bool {
+        false
+    }
+}
+
+/// Every letter costs one: the cost of a portmantout is its length.
+pub struct Length;
+
+impl CostModel for Length {
+    fn overlap(&self, overlap: usize) -> i64 {
+        -(overlap as i64)
+    }
+
+    fn padding(&self, _from: &[u8], padding: &[u8], _to: &[u8]) -> i64 {
+        padding.len() as i64
+    }
+
+    fn min_padding(&self, len: usize) -> i64 {
+        len as i64
+    }
+}
+
+/// Like `Length`, but each letter of padding costs `weight`.
+pub struct WeightedPadding {
+    pub weight: i64,
+}
+
+impl CostModel for WeightedPadding {
+    fn overlap(&self, overlap: usize) -> i64 {
+        -(overlap as i64)
+    }
+
+    fn padding(&self, _from: &[u8], padding: &[u8], _to: &[u8]) -> i64 {
+        self.weight * padding.len() as i64
+    }
+
+    fn min_padding(&self, len: usize) -> i64 {
+        self.weight * len as i64
+    }
+}
+
+/// What every padded edge costs in `PaddedEdges`: more than any difference in length, so that
+/// the number of padded edges always comes first.
+pub const PADDED_EDGE: i64 = 1 << 32;
+
+/// Fewest padded edges (even ones with no padding letters), and then shortest. The cost of a
+/// portmantout is its number of padded edges times `PADDED_EDGE`, plus its length.
+pub struct PaddedEdges;
+
+impl CostModel for PaddedEdges {
+    fn overlap(&self, overlap: usize) -> i64 {
+        -(overlap as i64)
+    }
+
+    fn padding(&self, _from: &[u8], padding: &[u8], _to: &[u8]) -> i64 {
+        PADDED_EDGE + padding.len() as i64
+    }
+
+    fn min_padding(&self, len: usize) -> i64 {
+        PADDED_EDGE + len as i64
+    }
+
+    fn overlaps_first(&self) -> bool {
+        true
+    }
+}
+
+/// Parses a `--cost` argument: "length", "padding-weight:N" or "padded-edges".
+pub fn parse(spec: &str) -> Result<Box<dyn CostModel>, String> {
+    let mut parts = spec.splitn(2, ':');
+    match (parts.next(), parts.next()) {
+        (Some("length"), None) => Ok(Box::new(Length)),
+        (Some("padded-edges"), None) => Ok(Box::new(PaddedEdges)),
+        (Some("padding-weight"), Some(weight)) => {
+            let weight = weight.parse().map_err(|_| format!("bad padding weight {:?}", weight))?;
+            if weight < 1 {
+                return Err("the padding weight must be at least 1".to_string());
+            }
+            Ok(Box::new(WeightedPadding { weight }))
+        }
+        _ => Err(format!("unknown cost model {:?}; try length, padding-weight:N or padded-edges", spec)),
+    }
+}
+
+#[test]
+fn test_parse() {
+    let model = parse("padding-weight:3").unwrap();
+    assert_eq!(model.padding(b"cat", b"xy", b"dog"), 6);
+    assert_eq!(model.overlap(2), -2);
+    assert_eq!(parse("length").unwrap().min_padding(4), 4);
+    assert!(parse("padding-weight:0").is_err());
+    assert!(parse("words").is_err());
+
+    // Even an empty padding outweighs a long portmantout, and then shorter is better.
+    let model = parse("padded-edges").unwrap();
+    assert!(model.padding(b"cat", b"", b"dog") > 1_000_000_000);
+    assert!(model.padding(b"cat", b"x", b"dog") > model.padding(b"cat", b"", b"dog"));
+    assert!(model.overlaps_first() && !parse("length").unwrap().overlaps_first());
+}
@@ -0,0 +1,217 @@
+//! Finds a provably shortest portmantout of a small reduced wordlist, by Held–Karp dynamic
+//! programming over subsets of the reduced words. The cost of following one reduced word by
+//! another is exact and independent of the rest (see `carrycoat::links`), so the cheapest
+//! path through all of them is the shortest portmantout.
+//!
+//! Writes the portmantout to a file (`--output`, by default out/exact-LENGTH.txt), and with
+//! `--certificate FILE` writes a JSON certificate: the words, the cost of every link with the
+//! words that realize it, and the optimal order and length. Memory use is 2^n * n * 2 bytes, so
+//! we only take up to MAX_WORDS words.
+
+extern crate carrycoat;
+
+use std::convert::TryFrom;
+
+use carrycoat::json_string;
+use carrycoat::links::{self, Dictionary, Link};
+
+const MAX_WORDS: usize = 22;
+
+const UNREACHABLE: i16 = i16::MAX;
+
+/// The cheapest path that visits every node exactly once, as (cost, order). `costs[i][j]` is
+/// the cost of going from i to j.
+fn held_karp(costs: &[Vec<Option<i16>>]) -> Option<(i16, Vec<usize>)> {
+    let n = costs.len();
+    if n == 0 {
+        return Some((0, Vec::new()));
+    }
+    let full = (1usize << n) - 1;
+
+    // best[mask * n + last]: the cheapest path through the nodes in `mask` that ends at `last`.
+    let mut best = vec![UNREACHABLE; (full + 1) * n];
+    for last in 0..n {
+        best[(1 << last) * n + last] = 0;
+    }
+    for mask in 1..(full + 1) {
+        for last in 0..n {
+            let cost = best[mask * n + last];
+            if cost == UNREACHABLE {
+                continue;
+            }
+            for (next, &step) in costs[last].iter().enumerate() {
+                if mask & (1 << next) != 0 {
+                    continue;
+                }
+                if let Some(step) = step {
+                    let entry = &mut best[(mask | (1 << next)) * n + next];
+                    if cost + step < *entry {
+                        *entry = cost + step;
+                    }
+                }
+            }
+        }
+    }
+
+    let (mut last, &cost) = best[full * n..].iter().enumerate().min_by_key(|&(_, &cost)| cost)?;
+    if cost == UNREACHABLE {
+        return None;
+    }
+
+    // Walk back through the table to recover the order.
+    let mut order = vec![last];
+    let mut mask = full;
+    while mask != 1 << last {
+        let prev_mask = mask & !(1 << last);
+        let target = best[mask * n + last];
+        let prev = (0..n).find(|&prev| {
+            prev_mask & (1 << prev) != 0 && best[prev_mask * n + prev] != UNREACHABLE &&
+                costs[prev][last].is_some_and(|step| best[prev_mask * n + prev] + step == target)
+        }).expect("held-karp table is inconsistent");
+        order.push(prev);
+        mask = prev_mask;
+        last = prev;
+    }
+    order.reverse();
+    Some((cost, order))
+}
+
+#[test]
+fn test_held_karp() {
+    // Going around the "wrong" way is cheaper than the obvious first step.
+    let costs = vec![
+        vec![None, Some(1), Some(5)],
+        vec![Some(9), None, Some(9)],
+        vec![Some(-2), Some(3), None],
+    ];
+    assert_eq!(held_karp(&costs), Some((-1, vec![2, 0, 1])));
+    let costs = vec![vec![None, None], vec![None, None]];
+    assert_eq!(held_karp(&costs), None);
+}
+
+fn write_certificate(path: &str, words: &[Vec<u8>], links: &[Vec<Option<Link>>], order: &[usize],
+                     portmantout: &[u8]) -> ::std::io::Result<()> {
+    use std::io::Write;
+    let mut out = ::std::fs::File::create(path)?;
+    let words_json: Vec<String> = words.iter().map(|w| json_string(w)).collect();
+    writeln!(out, "{{")?;
+    writeln!(out, "  \"method\": \"held-karp\",")?;
+    writeln!(out, "  \"words\": [{}],", words_json.join(", "))?;
+    writeln!(out, "  \"links\": [")?;
+    let mut lines = Vec::new();
+    for (from, row) in links.iter().enumerate() {
+        for (to, link) in row.iter().enumerate() {
+            let detail = match *link {
+                None => continue,
+                Some(Link::Overlap(n)) => format!("\"overlap\": {}", n),
+                Some(Link::Padding { ref padding, ref chain }) => {
+                    let chain: Vec<String> = chain.iter().map(|w| json_string(w)).collect();
+                    format!("\"padding\": {}, \"chain\": [{}]", json_string(padding), chain.join(", "))
+                }
+            };
+            lines.push(format!("    {{\"from\": {}, \"to\": {}, \"cost\": {}, {}}}",
+                               from, to, link.as_ref().unwrap().cost(), detail));
+        }
+    }
+    writeln!(out, "{}", lines.join(",\n"))?;
+    writeln!(out, "  ],")?;
+    let order: Vec<String> = order.iter().map(|idx| idx.to_string()).collect();
+    writeln!(out, "  \"order\": [{}],", order.join(", "))?;
+    writeln!(out, "  \"length\": {},", portmantout.len())?;
+    writeln!(out, "  \"portmantout\": {}", json_string(portmantout))?;
+    writeln!(out, "}}")
+}
+
+fn main_result() -> ::std::result::Result<(), Box<dyn (::std::error::Error)>> {
+    use std::io::{BufRead, Write};
+
+    let mut output = None;
+    let mut certificate = None;
+    let mut args = Vec::new();
+    let mut arg_iter = ::std::env::args();
+    while let Some(arg) = arg_iter.next() {
+        match &arg[..] {
+            "--output" => {
+                output = Some(arg_iter.next().ok_or("--output requires FILE")?);
+            }
+            "--certificate" => {
+                certificate = Some(arg_iter.next().ok_or("--certificate requires FILE")?);
+            }
+            _ => args.push(arg),
+        }
+    }
+    if args.len() != 3 {
+        println!("usage: {} [--output FILE] [--certificate FILE] REDUCED_WORDLIST_FILE WORDLIST_FILE", args[0]);
+        return Ok(());
+    }
+
+    let mut reduced = Vec::new();
+    for maybe_word in ::std::io::BufReader::new(::std::fs::File::open(&args[1])?).split(b'\n') {
+        let word = maybe_word?;
+        if !word.is_empty() {
+            reduced.push(word);
+        }
+    }
+    if reduced.len() > MAX_WORDS {
+        return Err(format!("{} words is too many; we can only do up to {}", reduced.len(), MAX_WORDS).into());
+    }
+
+    let mut words = Vec::new();
+    for maybe_word in ::std::io::BufReader::new(::std::fs::File::open(&args[2])?).split(b'\n') {
+        let word = maybe_word?;
+        if !word.is_empty() {
+            words.push(word);
+        }
+    }
+    // The costs are only exact if no word reaches across a whole reduced word.
+    for r in &reduced {
+        if let Some(word) = words.iter().find(|w| *w != r && carrycoat::contains_subsequence(w, r)) {
+            return Err(format!("{:?} is contained in {:?}; reduce the wordlist first",
+                               ::std::str::from_utf8(r), ::std::str::from_utf8(word)).into());
+        }
+    }
+
+    let dictionary = Dictionary::new(&words);
+    let links = links::links(&reduced, &dictionary);
+    // The costs are i16 to save memory, so make sure that no path can overflow them.
+    let too_big = || format!("link costs are too big for paths through {} words", reduced.len());
+    let costs: Vec<Vec<Option<i16>>> = links.iter()
+        .map(|row| row.iter().map(|link| link.as_ref().map(|l| i16::try_from(l.cost())).transpose()).collect())
+        .collect::<Result<_, _>>()
+        .map_err(|_| too_big())?;
+    let most = costs.iter().flatten().flatten().map(|&cost| (cost as i64).abs()).max().unwrap_or(0);
+    if most * reduced.len() as i64 >= UNREACHABLE as i64 {
+        return Err(too_big().into());
+    }
+
+    let (cost, order) = held_karp(&costs).ok_or("there is no portmantout of these words")?;
+
+    let portmantout = links::assemble(&reduced, &order, |from, to| links[from][to].clone().unwrap());
+    let total_len: usize = reduced.iter().map(|w| w.len()).sum();
+    assert_eq!(portmantout.len() as i64, total_len as i64 + cost as i64);
+
+    let output = output.unwrap_or_else(|| format!("out/exact-{}.txt", portmantout.len()));
+    let mut file = ::std::fs::File::create(&output)?;
+    file.write_all(&portmantout)?;
+    file.write_all(b"\n")?;
+
+    if let Some(path) = certificate {
+        write_certificate(&path, &reduced, &links, &order, &portmantout)?;
+    }
+
+    println!("words: {}", reduced.len());
+    println!("total characters: {}", total_len);
+    println!("total link cost: {}", cost);
+    println!("optimal length: {}", portmantout.len());
+    println!("wrote {}", output);
+    Ok(())
+}
+
+pub fn main() {
+    match main_result() {
+        Ok(()) => {}
+        Err(e) => {
+            println!("error: {}", e);
+        }
+    }
+}
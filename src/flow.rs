@@ -0,0 +1,242 @@
+//! Minimum-cost flow, by the primal-dual method: repeatedly find shortest paths with Dijkstra,
+//! then push a blocking flow (Dinic-style) along all of them at once. Costs are small integers
+//! in our use, so there are few distinct shortest-path lengths and therefore few phases.
+
+use std::collections::{BinaryHeap, VecDeque};
+use std::cmp::Reverse;
+
+#[derive(Clone)]
+pub struct MinCostFlow {
+    // Arc 2k is a forward edge and arc 2k + 1 is its reverse.
+    to: Vec<usize>,
+    cap: Vec<i64>,
+    cost: Vec<i64>,
+    arcs_from: Vec<Vec<usize>>,
+    // Node potentials as of the end of the last `solve`.
+    potential: Vec<i64>,
+}
+
+const INFINITY: i64 = i64::MAX / 4;
+
+impl MinCostFlow {
+    pub fn new(node_count: usize) -> MinCostFlow {
+        MinCostFlow {
+            to: Vec::new(),
+            cap: Vec::new(),
+            cost: Vec::new(),
+            arcs_from: vec![Vec::new(); node_count],
+            potential: Vec::new(),
+        }
+    }
+
+    pub fn add_node(&mut self) -> usize {
+        self.arcs_from.push(Vec::new());
+        self.arcs_from.len() - 1
+    }
+
+    /// Adds an edge and returns its id, for use with `flow()`.
+    pub fn add_edge(&mut self, from: usize, to: usize, cap: i64, cost: i64) -> usize {
+        let id = self.to.len();
+        self.arcs_from[from].push(id);
+        self.to.push(to);
+        self.cap.push(cap);
+        self.cost.push(cost);
+        self.arcs_from[to].push(id + 1);
+        self.to.push(from);
+        self.cap.push(0);
+        self.cost.push(-cost);
+        id / 2
+    }
+
+    /// How much flow the edge with the given id carries.
+    pub fn flow(&self, edge: usize) -> i64 {
+        self.cap[2 * edge + 1]
+    }
+
+    /// Sets how much flow the edge with the given id carries, within its capacity. It is up to
+    /// the caller to keep the flow conserved.
+    pub fn set_flow(&mut self, edge: usize, flow: i64) {
+        let cap = self.cap[2 * edge] + self.cap[2 * edge + 1];
+        assert!(flow <= cap);
+        self.cap[2 * edge] = cap - flow;
+        self.cap[2 * edge + 1] = flow;
+    }
+
+    /// The potential of `node` after `solve`. These are optimal dual values: every arc that can
+    /// still carry flow has a nonnegative reduced cost `cost + potential(from) - potential(to)`.
+    pub fn potential(&self, node: usize) -> i64 {
+        self.potential[node]
+    }
+
+    /// Takes the edge with the given id out of the graph, along with any flow on it.
+    pub fn remove_edge(&mut self, edge: usize) {
+        self.cap[2 * edge] = 0;
+        self.cap[2 * edge + 1] = 0;
+    }
+
+    /// Sends up to `max_flow` units from `source` to `sink` as cheaply as possible.
+    /// Returns (flow, cost). The initial graph must not contain negative cycles.
+    pub fn solve(&mut self, source: usize, sink: usize, max_flow: i64) -> (i64, i64) {
+        let node_count = self.arcs_from.len();
+        let mut potential = self.initial_potential(source);
+        let mut total_flow = 0;
+        let mut total_cost = 0;
+
+        while total_flow < max_flow {
+            // Dijkstra on reduced costs.
+            let mut dist = vec![INFINITY; node_count];
+            dist[source] = 0;
+            let mut heap = BinaryHeap::new();
+            heap.push(Reverse((0, source)));
+            while let Some(Reverse((d, node))) = heap.pop() {
+                if d > dist[node] {
+                    continue;
+                }
+                for &arc in &self.arcs_from[node] {
+                    if self.cap[arc] <= 0 {
+                        continue;
+                    }
+                    let next = self.to[arc];
+                    let nd = d + self.cost[arc] + potential[node] - potential[next];
+                    if nd < dist[next] {
+                        dist[next] = nd;
+                        heap.push(Reverse((nd, next)));
+                    }
+                }
+            }
+            if dist[sink] >= INFINITY {
+                break;
+            }
+            for node in 0..node_count {
+                potential[node] += ::std::cmp::min(dist[node], dist[sink]);
+            }
+
+            let pushed = self.blocking_flow(source, sink, max_flow - total_flow, &potential);
+            total_flow += pushed;
+            total_cost += pushed * (potential[sink] - potential[source]);
+        }
+        self.potential = potential;
+        (total_flow, total_cost)
+    }
+
+    /// Shortest distances from `source` (Bellman-Ford, queue-based), so that reduced costs
+    /// start out nonnegative even if some edges have negative cost.
+    fn initial_potential(&self, source: usize) -> Vec<i64> {
+        let node_count = self.arcs_from.len();
+        let mut dist = vec![INFINITY; node_count];
+        let mut in_queue = vec![false; node_count];
+        let mut queue = VecDeque::new();
+        dist[source] = 0;
+        queue.push_back(source);
+        while let Some(node) = queue.pop_front() {
+            in_queue[node] = false;
+            for &arc in &self.arcs_from[node] {
+                if self.cap[arc] <= 0 {
+                    continue;
+                }
+                let next = self.to[arc];
+                if dist[node] + self.cost[arc] < dist[next] {
+                    dist[next] = dist[node] + self.cost[arc];
+                    if !in_queue[next] {
+                        in_queue[next] = true;
+                        queue.push_back(next);
+                    }
+                }
+            }
+        }
+        for d in dist.iter_mut() {
+            if *d >= INFINITY {
+                *d = 0;
+            }
+        }
+        dist
+    }
+
+    /// Maximum flow using only arcs of zero reduced cost.
+    fn blocking_flow(&mut self, source: usize, sink: usize, limit: i64, potential: &[i64]) -> i64 {
+        let node_count = self.arcs_from.len();
+        let mut total = 0;
+        loop {
+            // Breadth-first levels over the admissible arcs.
+            let mut level = vec![usize::MAX; node_count];
+            level[source] = 0;
+            let mut queue = VecDeque::new();
+            queue.push_back(source);
+            while let Some(node) = queue.pop_front() {
+                for &arc in &self.arcs_from[node] {
+                    let next = self.to[arc];
+                    if self.cap[arc] > 0 && level[next] == usize::MAX &&
+                        self.cost[arc] + potential[node] - potential[next] == 0
+                    {
+                        level[next] = level[node] + 1;
+                        queue.push_back(next);
+                    }
+                }
+            }
+            if level[sink] == usize::MAX {
+                return total;
+            }
+
+            // Depth-first search for augmenting paths, without recursion.
+            let mut current_arc = vec![0; node_count];
+            let mut path: Vec<usize> = Vec::new();
+            let mut node = source;
+            loop {
+                if total >= limit {
+                    return total;
+                }
+                if node == sink {
+                    let pushed = path.iter().map(|&arc| self.cap[arc]).min().unwrap();
+                    let pushed = ::std::cmp::min(pushed, limit - total);
+                    for &arc in &path {
+                        self.cap[arc] -= pushed;
+                        self.cap[arc ^ 1] += pushed;
+                    }
+                    total += pushed;
+                    // Back up to just before the first saturated arc.
+                    let saturated = path.iter().position(|&arc| self.cap[arc] == 0).unwrap();
+                    path.truncate(saturated);
+                    node = if saturated == 0 { source } else { self.to[path[saturated - 1]] };
+                    continue;
+                }
+                let mut advanced = false;
+                while current_arc[node] < self.arcs_from[node].len() {
+                    let arc = self.arcs_from[node][current_arc[node]];
+                    let next = self.to[arc];
+                    if self.cap[arc] > 0 && level[next] == level[node] + 1 &&
+                        self.cost[arc] + potential[node] - potential[next] == 0
+                    {
+                        path.push(arc);
+                        node = next;
+                        advanced = true;
+                        break;
+                    }
+                    current_arc[node] += 1;
+                }
+                if !advanced {
+                    // Dead end. Retreat, and don't try this node again.
+                    level[node] = usize::MAX;
+                    match path.pop() {
+                        Some(arc) => node = self.to[arc ^ 1],
+                        None => break,
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_assignment() {
+    // Assign rows 0..3 to columns 0..3 with costs[row][col].
+    let costs = [[4, 1, 3], [2, 0, 5], [3, 2, 2]];
+    let mut flow = MinCostFlow::new(8);
+    for (row, row_costs) in costs.iter().enumerate() {
+        flow.add_edge(0, 2 + row, 1, 0);
+        flow.add_edge(5 + row, 1, 1, 0);
+        for (col, cost) in row_costs.iter().enumerate() {
+            flow.add_edge(2 + row, 5 + col, 1, cost - 10);
+        }
+    }
+    assert_eq!(flow.solve(0, 1, 10), (3, 5 - 30));
+}
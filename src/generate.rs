@@ -1,116 +1,125 @@
-extern crate radix_trie;
-
-use std::collections::hash_set::HashSet;
-
-#[derive(PartialEq, Eq, Debug)]
-pub struct BytesTrieKey(Vec<u8>);
-
-impl ::radix_trie::TrieKey for BytesTrieKey {
-    fn encode(&self) -> Vec<u8> {
-        self.0.clone()
-    }
-}
-
-pub type Trie = ::radix_trie::Trie<BytesTrieKey, Option<usize>>;
-
-fn main_result() -> ::std::result::Result<(), Box<::std::error::Error>> {
+//! Greedily merges the words of a reduced wordlist into particles, always taking the biggest
+//! available overlap first (see `carrycoat::merge`).
+//!
+//! With `--provenance FILE`, also writes one line per particle to FILE: the particle, followed
+//! by a tab-separated `WORD:OFFSET:OVERLAP` entry for each of its reduced words, in order.
+//! OFFSET is where the word starts in the particle and OVERLAP is how many of its letters
+//! it shares with the previous word.
+//!
+//! With `--snapshot N` (which may be repeated), writes the particles to `overlapN.asc` in the
+//! snapshot directory (`--snapshot-dir`, by default the current directory) as soon as all
+//! overlaps longer than N have been merged. With `--stop-at-overlap N`, stops at that point.
+//!
+//! With `--matching`, each overlap level is treated as a bipartite matching problem between
+//! particle ends and particle starts. We merge a maximum matching that forms no cycles, all at
+//! once. The number of merges does not depend on the order in which candidates happen to be
+//! visited.
+//!
+//! With `--seed N`, ties between equal overlaps are broken at random, so different seeds give
+//! different particle sets. With `--matching` too, the seed decides which of the equally large
+//! matchings gets merged. `--random` does the same with a seed from /dev/urandom. Either way
+//! the seed gets printed, snapshots are named `overlapN-seedS.asc` instead, and the provenance
+//! file starts with a `#seed S` line, so that an interesting particle set can be regenerated.
+
+extern crate byteorder;
+extern crate carrycoat;
+
+use byteorder::{LittleEndian, ReadBytesExt};
+use carrycoat::MAX_OVERLAP;
+use carrycoat::merge::Merger;
+
+
+fn main_result() -> ::std::result::Result<(), Box<dyn (::std::error::Error)>> {
     use std::io::{BufRead};
 
-    let args : Vec<String> = ::std::env::args().collect();
+    let mut provenance = None;
+    let mut snapshots = Vec::new();
+    let mut snapshot_dir = ::std::path::PathBuf::from(".");
+    let mut stop_at_overlap = 0;
+    let mut matching = false;
+    let mut seed = None;
+    let mut args = Vec::new();
+    let mut arg_iter = ::std::env::args();
+    while let Some(arg) = arg_iter.next() {
+        match &arg[..] {
+            "--provenance" => {
+                let path = arg_iter.next().ok_or("--provenance requires a FILE")?;
+                provenance = Some(::std::io::BufWriter::new(::std::fs::File::create(path)?));
+            }
+            "--matching" => matching = true,
+            "--seed" => {
+                seed = Some(arg_iter.next().ok_or("--seed requires N")?.parse::<u64>()?);
+            }
+            "--random" => {
+                seed = Some(::std::fs::File::open("/dev/urandom")?.read_u64::<LittleEndian>()?);
+            }
+            "--snapshot" => {
+                snapshots.push(arg_iter.next().ok_or("--snapshot requires N")?.parse::<usize>()?);
+            }
+            "--snapshot-dir" => {
+                snapshot_dir = arg_iter.next().ok_or("--snapshot-dir requires a DIR")?.into();
+            }
+            "--stop-at-overlap" => {
+                stop_at_overlap = arg_iter.next().ok_or("--stop-at-overlap requires N")?.parse()?;
+            }
+            _ => args.push(arg),
+        }
+    }
     if args.len() != 2 {
-        println!("usage: {} REDUCED_WORDLIST_FILE", args[0]);
+        println!("usage: {} [--matching] [--seed N | --random] [--provenance FILE] [--snapshot N]... [--snapshot-dir DIR] [--stop-at-overlap N] \
+                  REDUCED_WORDLIST_FILE", args[0]);
         return Ok(());
     }
-
-    let mut trie = Trie::new();
-    let mut word_set = HashSet::<Vec<u8>>::new();
-    for maybe_word in ::std::io::BufReader::new(try!(::std::fs::File::open(&args[1]))).split('\n' as u8) {
-        let word = try!(maybe_word);
-        word_set.insert(word.clone());
-        trie.insert(BytesTrieKey(word), None);
+    // Snapshot N is taken after merging overlap N + 1, so only these levels ever get one.
+    if let Some(&n) = snapshots.iter().find(|&&n| n < stop_at_overlap || n >= MAX_OVERLAP) {
+        return Err(format!("--snapshot {} is never reached: snapshots go from --stop-at-overlap ({}) to {}",
+                           n, stop_at_overlap, MAX_OVERLAP - 1).into());
     }
-    assert_eq!(trie.len(), word_set.len());
-    println!("word count: {}", word_set.len());
 
-
-    let mut overlap_upper_bound = 16; // differentiation has 15 letters
-
-    // Let's just find the biggest overlap.
-    'outer: loop {
-        let mut most_overlap = 0;
-        let mut best_word = Vec::new();
-        'inner: for word in &word_set {
-            let start = if word.len() > overlap_upper_bound { word.len() - overlap_upper_bound } else { 1 };
-            for idx in start..word.len() {
-                let key = BytesTrieKey(word[idx..].to_vec());
-                match trie.get_descendant(&key) {
-                    Some(node) if node.len() > 0 => {
-                        let overlap_len = word.len() - idx;
-                        if overlap_len > most_overlap {
-                            'find_word: for key in node.keys() {
-                                // Don't form a cycle!
-                                if &key.0 != word {
-                                    most_overlap = overlap_len;
-                                    best_word = word.clone();
-                                    if most_overlap == overlap_upper_bound {
-                                        break 'inner;
-                                    } else {
-                                        break 'find_word;
-                                    }
-                                } /*  else {
-                                    panic!("Would have formed a cycle: {:?}", ::std::str::from_utf8(word));
-                                } */
-                            }
-
-                        }
-                    }
-                    _ => {}
-                }
-            }
+    let mut word_list = Vec::new();
+    for maybe_word in ::std::io::BufReader::new(::std::fs::File::open(&args[1])?).split(b'\n') {
+        word_list.push(maybe_word?);
+    }
+    println!("word count: {}", word_list.len());
+
+    // search and join need a particle that starts with "portmanteau".
+    let mut merger = Merger::new(word_list, MAX_OVERLAP).keep_at_start(b"portmanteau");
+    if let Some(seed) = seed {
+        println!("seed: {}", seed);
+        merger = merger.with_seed(seed);
+        if let Some(ref mut out) = provenance {
+            use std::io::Write;
+            writeln!(out, "#seed {}", seed)?;
         }
+    }
 
-        println!("most_overlap = {}", most_overlap);
-        if most_overlap == 0 {
-            break 'outer;
+    for k in ((stop_at_overlap + 1)..(MAX_OVERLAP + 1)).rev() {
+        let merges = if matching { merger.merge_level_matching(k) } else { merger.merge_level(k) };
+        if merges > 0 {
+            println!("overlap {}: {} merges. particle count: {}", k, merges, merger.particle_count());
         }
 
-        word_set.remove(&best_word);
-        trie.remove(&BytesTrieKey(best_word.clone()));
-
-        let overlap = &best_word[(best_word.len() - most_overlap)..];
-
-        let key = BytesTrieKey(overlap.to_vec());
-        let to_remove_key = BytesTrieKey(
-            trie.get_descendant(&key).expect("broken trie?").keys().next().expect("no key?").0.clone());
-
-        trie.remove(&to_remove_key);
-        let trie_word = to_remove_key.0;
-
-        word_set.remove(&trie_word);
-
-        let mut new_word = best_word.clone();
-        for idx in most_overlap .. trie_word.len() {
-            new_word.push(trie_word[idx]);
+        // Now the biggest remaining overlap is at most k - 1.
+        if snapshots.contains(&(k - 1)) {
+            let name = match seed {
+                Some(seed) => format!("overlap{}-seed{}.asc", k - 1, seed),
+                None => format!("overlap{}.asc", k - 1),
+            };
+            let path = snapshot_dir.join(name);
+            println!("writing snapshot {}", path.display());
+            merger.write_particles(&path)?;
         }
-
-        println!("new_word = {:?}", ::std::str::from_utf8(&new_word));
-        word_set.insert(new_word.clone());
-        let new_key = BytesTrieKey(new_word);
-        trie.insert(new_key, None);
-
-        overlap_upper_bound = most_overlap;
-
-        assert_eq!(word_set.len(), trie.len());
-        println!("particle count: {}", word_set.len());
     }
 
-
     println!("OUTPUT -----");
-    for word in word_set {
-        println!("{}", ::std::str::from_utf8(&word).unwrap());
+    for chain in merger.chains() {
+        println!("{}", ::std::str::from_utf8(&merger.particle_chars(&chain))?);
+        if let Some(ref mut out) = provenance {
+            merger.write_provenance(&chain, out)?;
+        }
     }
 
-    return Ok(());
+    Ok(())
 }
 
 pub fn main() {
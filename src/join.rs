@@ -1,27 +1,223 @@
-extern crate radix_trie;
+extern crate carrycoat;
 
-use std::collections::hash_map::HashMap;
+use carrycoat::MAX_OVERLAP;
+use carrycoat::chains::{BytesTrieKey, Edge, Trie};
+use carrycoat::cost::{self, CostModel};
+use carrycoat::joiners::{self, PaddingWords};
 
-#[derive(PartialEq, Eq, Debug)]
-pub struct BytesTrieKey(Vec<u8>);
+/// A way to continue the portmantout: an edge, then a particle. Either the portmantout already
+/// ends with the first letters of the particle, or `word` is the word that covers the padding
+/// (`word` is empty for overlaps). `cost` is what the join costs under the cost model (by
+/// default, how many letters it adds beyond the particle).
+#[derive(Clone)]
+struct Step {
+    edge: Edge,
+    particle: Vec<u8>,
+    word: Vec<u8>,
+    cost: i64,
+}
+
+impl Step {
+    /// Appends the padding, if any, and the rest of the particle.
+    fn append_to(&self, portmantout: &mut Vec<u8>) {
+        match self.edge {
+            Edge::Overlapped(n) => portmantout.extend_from_slice(&self.particle[n..]),
+            Edge::Padded { ref padding } => {
+                portmantout.extend_from_slice(padding);
+                portmantout.extend_from_slice(&self.particle);
+            }
+        }
+    }
+}
+
+/// Adds `step` to `best`, which is sorted by cost and keeps at most one step per particle.
+/// Later steps win ties.
+fn offer(best: &mut Vec<Step>, step: Step, count: usize) {
+    if let Some(pos) = best.iter().position(|other| other.particle == step.particle) {
+        if best[pos].cost < step.cost {
+            return;
+        }
+        best.remove(pos);
+    }
+    let pos = best.iter().position(|other| other.cost >= step.cost).unwrap_or(best.len());
+    best.insert(pos, step);
+    best.truncate(count);
+}
+
+/// Up to `count` of the cheapest steps from the end of `tail`, to distinct particles not in
+/// `exclude`.
+fn best_steps(tail: &[u8], padding_words: &PaddingWords, particles_trie: &Trie, model: &dyn CostModel,
+              exclude: &[Vec<u8>], count: usize) -> Vec<Step>
+{
+    let mut best: Vec<Step> = Vec::new();
+
+    // Best of all is a particle that starts with the end of the tail.
+    'overlaps: for overlap in (1..::std::cmp::min(MAX_OVERLAP, tail.len()) + 1).rev() {
+        let suffix = tail[tail.len() - overlap..].to_vec();
+        if let Some(node) = particles_trie.get_descendant(&BytesTrieKey(suffix)) {
+            for key in node.keys() {
+                let particle = &key.0;
+                if particle.len() <= overlap || exclude.contains(particle) {
+                    continue;
+                }
+                offer(&mut best, Step {
+                    edge: Edge::Overlapped(overlap),
+                    particle: particle.clone(),
+                    word: Vec::new(),
+                    cost: model.overlap(overlap),
+                }, count);
+                if best.len() == count {
+                    break 'overlaps;
+                }
+            }
+        }
+    }
+    if best.len() == count && best[count - 1].cost < model.min_padding(0) {
+        return best;
+    }
 
-impl ::radix_trie::TrieKey for BytesTrieKey {
-    fn encode(&self) -> Vec<u8> {
-        self.0.clone()
+    // Otherwise, pad with part of a word that starts in the tail and ends in the particle.
+    let start = tail.len() - ::std::cmp::min(padding_words.longest, tail.len());
+    'find_best: for suffix_start in start..tail.len() {
+        let suffix_len = tail.len() - suffix_start;
+        let suffix = tail[suffix_start ..].to_vec();
+        if let Some(node) = padding_words.trie.get_descendant(&BytesTrieKey(suffix)) {
+            assert!(node.len() > 0);
+            for key in node.keys() {
+                let word = &key.0;
+                'added: for idx in suffix_len .. word.len() {
+                    let padding_len = idx - suffix_len;
+                    if best.len() == count && best[count - 1].cost < model.min_padding(padding_len) {
+                        break 'added;
+                    }
+                    let particle_node = match particles_trie.get_descendant(&BytesTrieKey(word[idx..].to_vec())) {
+                        Some(particle_node) if !particle_node.is_empty() => particle_node,
+                        _ => continue,
+                    };
+                    for particle_key in particle_node.keys() {
+                        let particle = &particle_key.0;
+                        if exclude.contains(particle) {
+                            continue;
+                        }
+                        let padding = word[suffix_len..idx].to_vec();
+                        offer(&mut best, Step {
+                            cost: model.padding(tail, &padding, particle),
+                            edge: Edge::Padded { padding },
+                            particle: particle.clone(),
+                            word: word.clone(),
+                        }, count);
+                        if best.len() == count {
+                            break;
+                        }
+                    }
+                    if best.len() == count && best[count - 1].cost <= model.min_padding(0) {
+                        // We're not going to do better than this.
+                        break 'find_best;
+                    }
+                }
+            }
+        }
     }
+    best
 }
 
-pub type Trie = ::radix_trie::Trie<BytesTrieKey, ()>;
+/// Beam search: extends the `beam_width` cheapest partial continuations of the portmantout by
+/// `depth` particles, and returns the first step of the cheapest one.
+fn lookahead(portmantout: &[u8], padding_words: &PaddingWords, particles_trie: &Trie, model: &dyn CostModel,
+             beam_width: usize, depth: usize) -> Option<Step>
+{
+    struct Partial {
+        cost: i64,
+        tail: Vec<u8>,
+        particles: Vec<Vec<u8>>,
+        first: Option<Step>,
+    }
+
+    // Enough of the end for the longest overlap, or for a padding word to start in.
+    let tail_len = ::std::cmp::max(MAX_OVERLAP, padding_words.longest);
+    let tail_start = portmantout.len() - ::std::cmp::min(tail_len, portmantout.len());
+    let mut beam = vec![Partial {
+        cost: 0,
+        tail: portmantout[tail_start..].to_vec(),
+        particles: Vec::new(),
+        first: None,
+    }];
+    for _ in 0..depth {
+        let mut next_beam = Vec::new();
+        for partial in &beam {
+            for step in best_steps(&partial.tail, padding_words, particles_trie, model, &partial.particles, beam_width) {
+                let mut tail = partial.tail.clone();
+                step.append_to(&mut tail);
+                let tail = tail[tail.len() - ::std::cmp::min(tail_len, tail.len())..].to_vec();
+                let mut particles = partial.particles.clone();
+                particles.push(step.particle.clone());
+                next_beam.push(Partial {
+                    cost: partial.cost + step.cost,
+                    tail,
+                    particles,
+                    first: partial.first.clone().or(Some(step)),
+                });
+            }
+        }
+        if next_beam.is_empty() {
+            break;
+        }
+        next_beam.sort_by_key(|partial| partial.cost);
+        next_beam.truncate(beam_width);
+        beam = next_beam;
+    }
+    beam.into_iter().next().and_then(|partial| partial.first)
+}
 
 fn main_result() -> ::std::result::Result<(), Box<::std::error::Error>> {
     use std::io::{BufRead};
 
-    let args : Vec<String> = ::std::env::args().collect();
-    if args.len() != 4 {
-        println!("usage: {} PARTICLES_FILE JOINERS_FILE WORDLIST_FILE", args[0]);
+    let mut beam_width = 1;
+    let mut depth = 1;
+    let mut output = None;
+    let mut padding_max_len = joiners::DEFAULT_PADDING_MAX_LEN;
+    let mut cost_spec = None;
+    let mut args = Vec::new();
+    let mut arg_iter = ::std::env::args();
+    while let Some(arg) = arg_iter.next() {
+        match &arg[..] {
+            "--beam" => {
+                beam_width = arg_iter.next().ok_or("--beam requires B")?.parse()?;
+            }
+            "--depth" => {
+                depth = arg_iter.next().ok_or("--depth requires D")?.parse()?;
+            }
+            "--padding-max-len" => {
+                padding_max_len = arg_iter.next().ok_or("--padding-max-len requires N")?.parse()?;
+            }
+            "--output" => {
+                output = Some(arg_iter.next().ok_or("--output requires FILE")?);
+            }
+            "--cost" => {
+                cost_spec = Some(arg_iter.next().ok_or("--cost requires MODEL")?);
+            }
+            _ => args.push(arg),
+        }
+    }
+    if args.len() != 4 || beam_width == 0 || depth == 0 {
+        println!("usage: {} [--beam B] [--depth D] [--padding-max-len N] [--cost MODEL] [--output FILE] \
+                  PARTICLES_FILE JOINERS_FILE PADDING_WORDS_FILE",
+                 args[0]);
         return Ok(());
     }
 
+    let model = match cost_spec {
+        Some(ref spec) => cost::parse(spec)?,
+        None => Box::new(cost::Length),
+    };
+
+    // The join can take a while, so make sure there is somewhere to write the result first.
+    let output_dir = match output {
+        Some(ref output) => ::std::path::Path::new(output).parent().map(|dir| dir.to_path_buf()).unwrap_or_default(),
+        None => ::std::path::PathBuf::from("out"),
+    };
+    ::std::fs::create_dir_all(&output_dir)?;
+
     let mut particles = Vec::new();
     let mut particles_trie = Trie::new();
     for maybe_word in ::std::io::BufReader::new(try!(::std::fs::File::open(&args[1]))).split('\n' as u8) {
@@ -30,27 +226,9 @@ fn main_result() -> ::std::result::Result<(), Box<::std::error::Error>> {
         particles_trie.insert(BytesTrieKey(word), ());
     }
 
-    let mut words_trie = Trie::new();
-
-    let mut joiners = HashMap::<(u8, u8), Vec<u8>>::new();
-    for maybe_joiner in ::std::io::BufReader::new(try!(::std::fs::File::open(&args[2]))).split('\n' as u8) {
-        let joiner = try!(maybe_joiner);
-        words_trie.insert(BytesTrieKey(joiner.clone()), ());
-        let key = (*joiner.first().unwrap(), *joiner.last().unwrap());
-        if !joiners.contains_key(&key) {
-            joiners.insert(key, joiner);
-        }
-    }
+    let (joiners, padding_words) = joiners::load(&args[2], &args[3], padding_max_len)?;
 
-    for maybe_word in ::std::io::BufReader::new(try!(::std::fs::File::open(&args[3]))).split('\n' as u8) {
-        let word = try!(maybe_word);
-        if word.len() < 11 { // (optimization)
-            words_trie.insert(BytesTrieKey(word), ());
-        }
-    }
-
-
-    println!("words in word trie: {}", words_trie.len());
+    eprintln!("padding words: {} (longest {})", padding_words.len(), padding_words.longest);
 
     let mut portmantout = Vec::new();
 
@@ -58,69 +236,93 @@ fn main_result() -> ::std::result::Result<(), Box<::std::error::Error>> {
     let starticle = particles_trie.get_descendant(&key).and_then(|node| {node.keys().next()})
         .expect("no particle starts with 'portmanteau'?").0.clone();
 
+    // Nothing is ever joined onto the front of the starticle.
+    let missing = joiners.missing_pairs_between(particles.iter().map(|p| &p[..]),
+                                                particles.iter().filter(|&p| *p != starticle).map(|p| &p[..]));
+    if !missing.is_empty() {
+        return Err(format!("no joiner for letter pairs: {}", joiners::format_pairs(&missing)).into());
+    }
 
     for c in &starticle {
         portmantout.push(*c);
     }
 
+    let starticle_len = starticle.len();
     particles_trie.remove(&BytesTrieKey(starticle));
 
+    let mut total_overlap = 0;
+    let mut total_padding = 0;
+    let mut total_cost = 0;
+    let mut padded_steps = 0;
+    let mut paddings = Vec::new();
     'outer: while particles_trie.len() > 0 {
-        let mut best_padding: Option<Vec<u8>> = None;
-        let mut best_next_particle: Option<Vec<u8>> = None;
-        let mut overlap_word: Option<Vec<u8>> = None;
-        'find_best: for suffix_start in (portmantout.len() - 11)..(portmantout.len()) {
-            let suffix_len = portmantout.len() - suffix_start;
-            let suffix = portmantout[suffix_start ..].to_vec();
-            if let Some(node) = words_trie.get_descendant(&BytesTrieKey(suffix)) {
-                assert!(node.len() > 0);
-                for key in node.keys() {
-                    let word = key.0.clone();
-                    'added: for idx in suffix_len .. word.len() {
-                        let padding_len = idx - suffix_len;
-                        match best_padding {
-                            Some(ref p) if p.len() < padding_len => {
-                                break 'added;
-                            }
-                            _ => {}
-                        }
-                        match particles_trie.get_descendant(&BytesTrieKey(word[idx..].to_vec())) {
-                            Some(particle_node) if particle_node.len() > 0 => {
-                                let p = particle_node.keys().next().expect("no key?").0.clone();
-                                best_padding = Some(word[suffix_len..idx].to_vec());
-                                best_next_particle = Some(p);
-                                overlap_word = Some(word.clone());
-                                if padding_len == 0 {
-                                    break 'find_best;
-                                }
-                            }
-                            _ => {}
-                        }
-                    }
+        let step = if beam_width > 1 || depth > 1 {
+            lookahead(&portmantout, &padding_words, &particles_trie, &*model, beam_width, depth)
+        } else {
+            best_steps(&portmantout, &padding_words, &particles_trie, &*model, &[], 1).pop()
+        };
+        let step = step.unwrap_or_else(|| {
+            // No short word will do, so fall back on a joiner. We checked above that we have
+            // one for every pair of letters that could come up.
+            let particle = particles_trie.keys().next().expect("no key?").0.clone();
+            let joiner = joiners.get(*portmantout.last().unwrap(), particle[0]).expect("no joiner").to_vec();
+            let padding = joiner[1..joiner.len() - 1].to_vec();
+            let cost = model.padding(&portmantout, &padding, &particle);
+            Step { edge: Edge::Padded { padding }, particle, word: joiner, cost }
+        });
+        match step.edge {
+            Edge::Overlapped(n) => {
+                eprintln!("next: overlap {}, {:?}", n, ::std::str::from_utf8(&step.particle));
+                total_overlap += n;
+            }
+            Edge::Padded { ref padding } => {
+                eprintln!("next: {:?}, {:?}, {:?}",
+                          ::std::str::from_utf8(&step.word),
+                          ::std::str::from_utf8(padding),
+                          ::std::str::from_utf8(&step.particle));
+                total_padding += padding.len();
+                padded_steps += 1;
+                if !padding.is_empty() {
+                    paddings.push((padding.len(), paddings.len(), step.word.clone(), padding.clone()));
                 }
             }
         }
-        if let (Some(particle), Some(padding)) = (best_next_particle, best_padding) {
-            println!("next: {:?}, {:?}, {:?}",
-                     ::std::str::from_utf8(&overlap_word.unwrap()),
-                     ::std::str::from_utf8(&padding),
-                     ::std::str::from_utf8(&particle));
-            for idx in 0..padding.len() {
-                portmantout.push(padding[idx]);
-            }
-            for idx in 0..particle.len() {
-                portmantout.push(particle[idx]);
-            }
+        step.append_to(&mut portmantout);
+        total_cost += step.particle.len() as i64 + step.cost;
+        particles_trie.remove(&BytesTrieKey(step.particle));
+        eprintln!("trie len: {}", particles_trie.len());
+    }
 
-            particles_trie.remove(&BytesTrieKey(particle));
-            println!("trie len: {}", particles_trie.len());
-        } else {
-            unreachable!()
-        }
+    let output = output.unwrap_or_else(|| format!("out/join-{}.txt", portmantout.len()));
+    {
+        use std::io::Write;
+        let mut file = ::std::fs::File::create(&output)?;
+        file.write_all(&portmantout)?;
+        file.write_all(b"\n")?;
     }
 
-    println!("OUTPUT -----");
-    println!("{}", ::std::str::from_utf8(&portmantout).unwrap());
+    println!("particles: {}", particles.len());
+    println!("total overlap: {}", total_overlap);
+    println!("total padding: {} ({} paddings)", total_padding, paddings.len());
+    // Longest first, and in order of appearance among equals.
+    paddings.sort_by_key(|&(len, order, _, _)| (::std::cmp::Reverse(len), order));
+    println!("longest paddings:");
+    for (_, _, word, padding) in paddings.iter().take(10) {
+        println!("    {:?} in {:?}",
+                 ::std::str::from_utf8(padding).unwrap(),
+                 ::std::str::from_utf8(word).unwrap());
+    }
+    println!("padded edges: {}", padded_steps);
+    println!("length: {}", portmantout.len());
+    let cost_arg = match cost_spec {
+        Some(spec) => {
+            println!("cost: {}", starticle_len as i64 + total_cost);
+            format!("--cost {} ", spec)
+        }
+        None => String::new(),
+    };
+    println!("wrote {}; to improve on it, run: search {}{} {} {} {}",
+             output, cost_arg, args[1], args[2], args[3], output);
 
     return Ok(());
 }
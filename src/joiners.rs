@@ -0,0 +1,179 @@
+//! The joiner table: for each pair of letters, a word that starts with the first and ends with
+//! the second. Any particle can follow any other by way of the joiner for the last letter of
+//! the one and the first letter of the other, which overlaps each of them by a letter.
+//!
+//! Shorter paddings come from `PaddingWords`: the joiners, plus the short words of the
+//! dictionary.
+
+use std::collections::{BTreeSet, HashMap};
+
+use chains::{BytesTrieKey, Trie};
+
+/// How long a dictionary word can be and still be used for padding, unless `--padding-max-len`
+/// says otherwise.
+pub const DEFAULT_PADDING_MAX_LEN: usize = 10;
+
+#[derive(Default)]
+pub struct Joiners {
+    by_ends: HashMap<(u8, u8), Vec<u8>>,
+}
+
+impl Joiners {
+    pub fn new() -> Joiners {
+        Joiners::default()
+    }
+
+    /// Adds a joiner, unless we already have one for the same pair of letters.
+    pub fn insert(&mut self, joiner: Vec<u8>) {
+        if joiner.len() < 2 {
+            return;
+        }
+        let key = (joiner[0], joiner[joiner.len() - 1]);
+        self.by_ends.entry(key).or_insert(joiner);
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_ends.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_ends.is_empty()
+    }
+
+    pub fn get(&self, first: u8, last: u8) -> Option<&[u8]> {
+        self.by_ends.get(&(first, last)).map(|joiner| &joiner[..])
+    }
+
+    /// The padding that goes between `from` and `to` if we join them with a joiner: the joiner,
+    /// minus the letters it shares with them.
+    pub fn padding(&self, from: &[u8], to: &[u8]) -> Option<&[u8]> {
+        self.get(*from.last()?, *to.first()?).map(|joiner| &joiner[1..joiner.len() - 1])
+    }
+
+    /// The letter pairs that some particle could need to be joined by, but that have no joiner.
+    pub fn missing_pairs<'a, I>(&self, particles: I) -> Vec<(u8, u8)>
+        where I: IntoIterator<Item = &'a [u8]>
+    {
+        let particles: Vec<&[u8]> = particles.into_iter().collect();
+        self.missing_pairs_between(particles.iter().cloned(), particles.iter().cloned())
+    }
+
+    /// Like `missing_pairs`, where particles from `from` are followed by particles from `to`.
+    /// join and search leave the starticle out of `to`, since nothing is ever joined onto its
+    /// front (except in search's cyclic mode).
+    pub fn missing_pairs_between<'a, 'b, I, J>(&self, from: I, to: J) -> Vec<(u8, u8)>
+        where I: IntoIterator<Item = &'a [u8]>, J: IntoIterator<Item = &'b [u8]>
+    {
+        let last_letters: BTreeSet<u8> = from.into_iter().filter_map(|p| p.last().cloned()).collect();
+        let first_letters: BTreeSet<u8> = to.into_iter().filter_map(|p| p.first().cloned()).collect();
+        let mut missing = Vec::new();
+        for &last in &last_letters {
+            for &first in &first_letters {
+                if !self.by_ends.contains_key(&(last, first)) {
+                    missing.push((last, first));
+                }
+            }
+        }
+        missing
+    }
+}
+
+/// The words that padding can come from: every joiner, plus the dictionary words no longer than
+/// `max_len`.
+pub struct PaddingWords {
+    pub trie: Trie,
+    pub max_len: usize,
+    // How far back from the end of a particle a padding word could start.
+    pub longest: usize,
+}
+
+impl PaddingWords {
+    pub fn new(max_len: usize) -> PaddingWords {
+        PaddingWords { trie: Trie::new(), max_len, longest: 0 }
+    }
+
+    pub fn insert_joiner(&mut self, joiner: Vec<u8>) {
+        self.longest = ::std::cmp::max(self.longest, joiner.len());
+        self.trie.insert(BytesTrieKey(joiner), ());
+    }
+
+    /// Adds a dictionary word, if it is short enough.
+    pub fn insert(&mut self, word: Vec<u8>) {
+        if word.len() <= self.max_len {
+            self.insert_joiner(word);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.trie.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.trie.is_empty()
+    }
+
+    pub fn words(&self) -> Vec<Vec<u8>> {
+        self.trie.keys().map(|key| key.0.clone()).collect()
+    }
+}
+
+/// Reads the joiner table from `joiners_path`, and padding words from it and from the
+/// dictionary at `words_path`.
+pub fn load<P, Q>(joiners_path: P, words_path: Q, padding_max_len: usize)
+                  -> ::std::io::Result<(Joiners, PaddingWords)>
+    where P: AsRef<::std::path::Path>, Q: AsRef<::std::path::Path>
+{
+    use std::io::BufRead;
+
+    let mut joiners = Joiners::new();
+    let mut padding_words = PaddingWords::new(padding_max_len);
+    for maybe_joiner in ::std::io::BufReader::new(::std::fs::File::open(joiners_path)?).split(b'\n') {
+        let joiner = maybe_joiner?;
+        padding_words.insert_joiner(joiner.clone());
+        joiners.insert(joiner);
+    }
+    for maybe_word in ::std::io::BufReader::new(::std::fs::File::open(words_path)?).split(b'\n') {
+        padding_words.insert(maybe_word?);
+    }
+    Ok((joiners, padding_words))
+}
+
+/// Describes letter pairs like "ab, xq", for error messages.
+pub fn format_pairs(pairs: &[(u8, u8)]) -> String {
+    let pairs: Vec<String> = pairs.iter().map(|&(a, b)| format!("{}{}", a as char, b as char)).collect();
+    pairs.join(", ")
+}
+
+#[test]
+fn test_joiners() {
+    let mut joiners = Joiners::new();
+    joiners.insert(b"anna".to_vec());
+    joiners.insert(b"abba".to_vec());
+    joiners.insert(b"bob".to_vec());
+    assert_eq!(joiners.padding(b"pizza", b"ant"), Some(&b"nn"[..]));
+    assert_eq!(joiners.padding(b"cab", b"bat"), Some(&b"o"[..]));
+    let particles: Vec<&[u8]> = vec![b"aa", b"bb"];
+    assert_eq!(joiners.missing_pairs(particles), vec![(b'a', b'b'), (b'b', b'a')]);
+    assert_eq!(format_pairs(&[(b'a', b'b'), (b'b', b'a')]), "ab, ba");
+
+    let mut padding_words = PaddingWords::new(3);
+    padding_words.insert_joiner(b"abba".to_vec());
+    padding_words.insert(b"cat".to_vec());
+    padding_words.insert(b"goat".to_vec());
+    assert_eq!(padding_words.words(), vec![b"abba".to_vec(), b"cat".to_vec()]);
+    assert_eq!(padding_words.longest, 4);
+}
+
+#[test]
+fn test_missing_pairs_between() {
+    let mut joiners = Joiners::new();
+    for joiner in &[&b"xx"[..], b"xoy", b"yox", b"yy"] {
+        joiners.insert(joiner.to_vec());
+    }
+    let particles: Vec<&[u8]> = vec![b"portmanteaux", b"xy", b"yx"];
+    // Only the starticle starts with "p", and there are no joiners into it.
+    assert_eq!(joiners.missing_pairs(particles.clone()), vec![(b'x', b'p'), (b'y', b'p')]);
+    // Once the starticle can't be joined onto, every pair is covered.
+    assert_eq!(joiners.missing_pairs_between(particles.clone(), particles[1..].to_vec()), vec![]);
+    assert_eq!(joiners.missing_pairs_between(particles, vec![&b"px"[..]]), vec![(b'x', b'p'), (b'y', b'p')]);
+}
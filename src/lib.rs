@@ -1,3 +1,19 @@
+extern crate radix_trie;
+extern crate rand;
+
+pub mod chains;
+pub mod cost;
+pub mod flow;
+pub mod joiners;
+pub mod links;
+pub mod merge;
+pub mod particle_graph;
+pub mod superstring;
+
+/// The longest overlap, inclusive, that we ever look for between two words or particles
+/// (differentiation has 15 letters).
+pub const MAX_OVERLAP: usize = 16;
+
 pub fn contains_subsequence(haystack: &[u8], needle: &[u8]) -> bool {
     if needle.len() > haystack.len() {
         return false;
@@ -13,9 +29,35 @@ pub fn contains_subsequence(haystack: &[u8], needle: &[u8]) -> bool {
     return false;
 }
 
+/// `chars` as a JSON string literal. Bytes that aren't UTF-8 become U+FFFD.
+pub fn json_string(chars: &[u8]) -> String {
+    let mut result = String::from("\"");
+    for c in String::from_utf8_lossy(chars).chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
 #[test]
 fn test_subseq() {
     assert!(contains_subsequence(&[1,3,4], &[3,4]));
     assert!(!contains_subsequence(&[1,3,4], &[3,5]));
     assert!(!contains_subsequence(&[1,3,4], &[3,4,5]));
 }
+
+#[test]
+fn test_json_string() {
+    assert_eq!(json_string(b"out/a b.txt"), "\"out/a b.txt\"");
+    assert_eq!(json_string(b"say \"hi\"\\\n\x01"), "\"say \\\"hi\\\"\\\\\\n\\u0001\"");
+    // Debug formatting would escape these, but JSON doesn't need to.
+    assert_eq!(json_string("caf\u{e9}\u{7f}".as_bytes()), "\"caf\u{e9}\u{7f}\"");
+}
@@ -0,0 +1,334 @@
+//! Exact costs of putting one reduced word right after another in a portmantout.
+//!
+//! Either the second word overlaps the end of the first, and then the more the better, or
+//! there is padding between them. Every two adjacent letters of the portmantout have to be in
+//! some word, so the padding has to be covered by a chain of dictionary words, each
+//! overlapping the one before it, that starts in the first reduced word and ends in the second.
+//! No dictionary word contains a reduced word (other than itself), so no word of the chain
+//! reaches past either of them, and the cost of each pair doesn't depend on anything else.
+
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap, HashMap, HashSet};
+
+/// How one reduced word follows another.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Link {
+    Overlap(usize),
+    /// `chain` is the dictionary words that cover the padding, in order.
+    Padding { padding: Vec<u8>, chain: Vec<Vec<u8>> },
+}
+
+impl Link {
+    /// How many letters the link adds to the portmantout, beyond the words themselves.
+    pub fn cost(&self) -> i64 {
+        match *self {
+            Link::Overlap(n) => -(n as i64),
+            Link::Padding { ref padding, .. } => padding.len() as i64,
+        }
+    }
+}
+
+/// The words that can cover padding, indexed by their proper prefixes.
+pub struct Dictionary<'a> {
+    words: &'a [Vec<u8>],
+    by_prefix: HashMap<&'a [u8], Vec<usize>>,
+}
+
+/// The words that paddings can lead to, indexed by their prefixes. Building the index takes
+/// about as long as finding the paddings from one word, so reuse it across words.
+pub struct Targets<'a> {
+    count: usize,
+    by_prefix: HashMap<&'a [u8], Vec<usize>>,
+}
+
+impl<'a> Targets<'a> {
+    pub fn new(targets: &'a [Vec<u8>]) -> Targets<'a> {
+        let mut by_prefix = HashMap::<&[u8], Vec<usize>>::new();
+        for (idx, target) in targets.iter().enumerate() {
+            for len in 1..(target.len() + 1) {
+                by_prefix.entry(&target[..len]).or_default().push(idx);
+            }
+        }
+        Targets { count: targets.len(), by_prefix }
+    }
+}
+
+impl<'a> Dictionary<'a> {
+    pub fn new(words: &'a [Vec<u8>]) -> Dictionary<'a> {
+        let mut by_prefix = HashMap::<&[u8], Vec<usize>>::new();
+        for (idx, word) in words.iter().enumerate() {
+            for len in 1..word.len() {
+                by_prefix.entry(&word[..len]).or_default().push(idx);
+            }
+        }
+        Dictionary { words, by_prefix }
+    }
+
+    fn longer_than(&self, prefix: &[u8]) -> &[usize] {
+        self.by_prefix.get(prefix).map(|idxs| &idxs[..]).unwrap_or(&[])
+    }
+
+    /// The cheapest padding from `from` to each of `targets`, if there is any.
+    pub fn paddings(&self, from: &[u8], targets: &Targets) -> Vec<Option<Link>> {
+        self.paddings_to(from, targets, targets.count)
+    }
+
+    /// The `count` cheapest paddings from `from` to any of `targets`, as (target index, link),
+    /// cheapest first. This only searches as far as it has to, unlike `paddings`.
+    pub fn cheapest_paddings(&self, from: &[u8], targets: &Targets, count: usize) -> Vec<(usize, Link)> {
+        let mut paddings: Vec<(usize, Link)> = self.paddings_to(from, targets, count).into_iter().enumerate()
+            .filter_map(|(idx, padding)| padding.map(|padding| (idx, padding)))
+            .collect();
+        paddings.sort_by_key(|&(idx, ref padding)| (padding.cost(), idx));
+        paddings.truncate(count);
+        paddings
+    }
+
+    /// Paddings from `from` to targets, exact for (at least) the `wanted` cheapest.
+    fn paddings_to(&self, from: &[u8], targets: &Targets, wanted: usize) -> Vec<Option<Link>> {
+        // Most paddings are short, and looking for long ones is expensive, so we look for
+        // paddings shorter than `limit` first.
+        let mut limit = 1;
+        loop {
+            if let Some(paddings) = self.paddings_within(from, targets.count, &targets.by_prefix, wanted, limit) {
+                return paddings;
+            }
+            limit *= 2;
+        }
+    }
+
+    /// Like `paddings_to`, but only finds paddings shorter than `limit`. Returns None if there
+    /// might be longer ones among the `wanted` cheapest.
+    ///
+    /// This is Dijkstra's algorithm over the words of a chain, by where they start relative to
+    /// the end of `from`. Each word of a chain starts after the one before it, and a target can
+    /// start no earlier than the last word of its chain, so once we get to words that start
+    /// after the best padding so far of the `wanted`th closest target, we are done.
+    fn paddings_within(&self, from: &[u8], target_count: usize, target_prefixes: &HashMap<&[u8], Vec<usize>>,
+                       wanted: usize, limit: isize) -> Option<Vec<Option<Link>>> {
+        // By word: where it starts, and the word before it in the chain.
+        let mut start: Vec<(isize, Option<usize>)> = vec![(isize::MAX, None); self.words.len()];
+        let mut heap = BinaryHeap::new();
+        for idx in 0..from.len() {
+            let word_start = idx as isize - from.len() as isize;
+            for &word_idx in self.longer_than(&from[idx..]) {
+                if word_start < start[word_idx].0 {
+                    start[word_idx] = (word_start, None);
+                    heap.push(Reverse((word_start, word_idx)));
+                }
+            }
+        }
+
+        // By target: where it starts, and the last word of its chain. `found` holds the same
+        // starts in order, as (start, target).
+        let mut best: Vec<Option<(isize, usize)>> = vec![None; target_count];
+        let mut found = BTreeSet::new();
+        let mut worst = limit;
+        let mut complete = true;
+        while let Some(Reverse((word_start, word_idx))) = heap.pop() {
+            if word_start >= worst {
+                complete &= worst < limit;
+                break;
+            }
+            if start[word_idx].0 < word_start {
+                continue;
+            }
+            let word = &self.words[word_idx];
+            let word_end = word_start + word.len() as isize;
+
+            // Targets that would start where we won't look anyway are not worth looking up.
+            let shortest = ::std::cmp::max(1, word_end - worst + 1) as usize;
+            if shortest > 1 {
+                complete &= worst < limit;
+            }
+            for len in shortest..(word.len() + 1) {
+                let target_start = word_end - len as isize;
+                if target_start < 0 {
+                    break;
+                }
+                for &target_idx in target_prefixes.get(&word[word.len() - len..]).map(|t| &t[..]).unwrap_or(&[]) {
+                    if best[target_idx].is_none_or(|(s, _)| target_start < s) {
+                        if let Some((s, _)) = best[target_idx] {
+                            found.remove(&(s, target_idx));
+                        }
+                        best[target_idx] = Some((target_start, word_idx));
+                        found.insert((target_start, target_idx));
+                    }
+                }
+            }
+            if wanted > 0 && found.len() >= wanted {
+                let &(kth, _) = if found.len() == wanted { found.iter().next_back() } else { found.iter().nth(wanted - 1) }
+                    .unwrap();
+                worst = ::std::cmp::min(limit, kth);
+            }
+
+            // A next word that starts inside `from` is one of the words we started with.
+            for len in (1..::std::cmp::min(word.len() as isize, word_end + 1) as usize).rev() {
+                let next_start = word_end - len as isize;
+                if next_start >= worst {
+                    complete &= worst < limit;
+                    break;
+                }
+                for &next_idx in self.longer_than(&word[word.len() - len..]) {
+                    if next_start < start[next_idx].0 {
+                        start[next_idx] = (next_start, Some(word_idx));
+                        heap.push(Reverse((next_start, next_idx)));
+                    }
+                }
+            }
+        }
+        if !complete && found.range(..(limit, 0)).count() < wanted {
+            return None;
+        }
+
+        Some(best.into_iter().map(|b| b.map(|(target_start, last_idx)| {
+            let mut chain = Vec::new();
+            let mut padding = vec![0; target_start as usize];
+            let mut current = Some(last_idx);
+            while let Some(word_idx) = current {
+                let (word_start, prev) = start[word_idx];
+                let word = &self.words[word_idx];
+                for (offset, &c) in word.iter().enumerate() {
+                    let pos = word_start + offset as isize;
+                    if pos >= 0 && pos < target_start {
+                        padding[pos as usize] = c;
+                    }
+                }
+                chain.push(word.clone());
+                current = prev;
+            }
+            chain.reverse();
+            Link::Padding { padding, chain }
+        })).collect())
+    }
+}
+
+/// The most that the start of `to` can overlap the end of `from`, short of containing either.
+pub fn max_overlap(from: &[u8], to: &[u8]) -> usize {
+    let max = ::std::cmp::min(from.len(), to.len());
+    (1..max).rev().find(|&len| from.ends_with(&to[..len])).unwrap_or(0)
+}
+
+/// The cheapest link from `from` to `to`, if there is any.
+pub fn link(from: &[u8], to: &[u8], dictionary: &Dictionary) -> Option<Link> {
+    match max_overlap(from, to) {
+        0 => dictionary.paddings(from, &Targets::new(&[to.to_vec()])).pop().unwrap(),
+        overlap => Some(Link::Overlap(overlap)),
+    }
+}
+
+/// The cheapest link from `words[from_idx]` to each word (None for itself, or if there is no
+/// way at all). `targets` must be `Targets::new(words)`.
+pub fn links_from(from_idx: usize, words: &[Vec<u8>], targets: &Targets, dictionary: &Dictionary)
+                  -> Vec<Option<Link>> {
+    let from = &words[from_idx];
+    let paddings = dictionary.paddings(from, targets);
+    paddings.into_iter().enumerate().map(|(to_idx, padding)| {
+        if to_idx == from_idx {
+            return None;
+        }
+        match max_overlap(from, &words[to_idx]) {
+            0 => padding,
+            overlap => Some(Link::Overlap(overlap)),
+        }
+    }).collect()
+}
+
+/// The `count` cheapest links from `words[from_idx]` to other words, as (word index, link),
+/// cheapest first. Unlike `links_from`, this doesn't look at every pair, which makes it usable
+/// on large sets of words. `targets` must be `Targets::new(words)`.
+pub fn cheapest_links_from(from_idx: usize, words: &[Vec<u8>], targets: &Targets, dictionary: &Dictionary,
+                           count: usize) -> Vec<(usize, Link)> {
+    let from = &words[from_idx];
+    // Any overlap is cheaper than any padding, and longer overlaps are cheaper than shorter ones.
+    let mut result = Vec::new();
+    let mut overlapping = HashSet::new();
+    overlapping.insert(from_idx);
+    'overlaps: for len in (1..from.len()).rev() {
+        for &to_idx in targets.by_prefix.get(&from[from.len() - len..]).map(|t| &t[..]).unwrap_or(&[]) {
+            if result.len() == count {
+                break 'overlaps;
+            }
+            if len < words[to_idx].len() && overlapping.insert(to_idx) {
+                result.push((to_idx, Link::Overlap(len)));
+            }
+        }
+    }
+    if result.len() < count {
+        // Some of the cheapest paddings go to words that we overlap instead.
+        let wanted = count - result.len() + overlapping.len();
+        let paddings = dictionary.cheapest_paddings(from, targets, wanted).into_iter()
+            .filter(|&(to_idx, _)| !overlapping.contains(&to_idx));
+        result.extend(paddings.take(count - result.len()));
+    }
+    result
+}
+
+/// The cheapest link from each word to each other word.
+pub fn links(words: &[Vec<u8>], dictionary: &Dictionary) -> Vec<Vec<Option<Link>>> {
+    let targets = Targets::new(words);
+    (0..words.len()).map(|from_idx| links_from(from_idx, words, &targets, dictionary)).collect()
+}
+
+/// Puts the words together in the given order, using `link` to get from one to the next.
+pub fn assemble<F>(words: &[Vec<u8>], order: &[usize], mut link: F) -> Vec<u8>
+    where F: FnMut(usize, usize) -> Link
+{
+    let mut portmantout = match order.first() {
+        Some(&first) => words[first].clone(),
+        None => return Vec::new(),
+    };
+    for pair in order.windows(2) {
+        let to = &words[pair[1]];
+        match link(pair[0], pair[1]) {
+            Link::Overlap(n) => portmantout.extend_from_slice(&to[n..]),
+            Link::Padding { padding, .. } => {
+                portmantout.extend_from_slice(&padding);
+                portmantout.extend_from_slice(to);
+            }
+        }
+    }
+    portmantout
+}
+
+#[test]
+fn test_paddings() {
+    let words: Vec<Vec<u8>> = ["cat", "tar", "arm", "dog", "go", "gnu"].iter()
+        .map(|w| w.as_bytes().to_vec()).collect();
+    let dictionary = Dictionary::new(&words);
+    let targets = vec![b"moo".to_vec(), b"rat".to_vec(), b"nut".to_vec()];
+    let paddings = dictionary.paddings(b"cat", &Targets::new(&targets));
+    // "cat" "tar" "arm" chain to "moo", and "tar" runs right into "rat".
+    assert_eq!(paddings[0], Some(Link::Padding {
+        padding: b"ar".to_vec(),
+        chain: vec![b"tar".to_vec(), b"arm".to_vec()],
+    }));
+    assert_eq!(paddings[1], Some(Link::Padding { padding: b"a".to_vec(), chain: vec![b"tar".to_vec()] }));
+    assert_eq!(paddings[2], None);
+    assert_eq!(max_overlap(b"cat", b"tar"), 1);
+    assert_eq!(max_overlap(b"cat", b"dog"), 0);
+    assert_eq!(max_overlap(b"tar", b"tar"), 0);
+}
+
+#[test]
+fn test_cheapest_links_from() {
+    let dictionary_words: Vec<Vec<u8>> = ["cat", "tar", "arm", "dog", "go", "gnu"].iter()
+        .map(|w| w.as_bytes().to_vec()).collect();
+    let dictionary = Dictionary::new(&dictionary_words);
+    let words: Vec<Vec<u8>> = ["cat", "moo", "rat", "nut", "atlas", "tsar", "ratchet"].iter()
+        .map(|w| w.as_bytes().to_vec()).collect();
+    let targets = Targets::new(&words);
+    let all = links_from(0, &words, &targets, &dictionary);
+    for count in 0..(words.len() + 1) {
+        let cheapest = cheapest_links_from(0, &words, &targets, &dictionary, count);
+        let mut expected: Vec<(i64, usize)> = all.iter().enumerate()
+            .filter_map(|(idx, link)| link.as_ref().map(|link| (link.cost(), idx)))
+            .collect();
+        expected.sort();
+        expected.truncate(count);
+        assert_eq!(cheapest.iter().map(|&(idx, ref link)| (link.cost(), idx)).collect::<Vec<_>>(), expected);
+        for (idx, link) in cheapest {
+            assert_eq!(Some(link), all[idx]);
+        }
+    }
+}
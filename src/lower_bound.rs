@@ -1,4 +1,45 @@
+//! Lower bounds on the length of a portmantout.
+//!
+//! By default, greedily forms cycles of maximum overlap among the reduced words and prints them.
+//! Greedy merging need not find a cycle cover of maximum total overlap, so the bound comes from
+//! the assignment problem over the words, as below but with no padding: the total length of
+//! the words minus the maximum cycle-cover overlap bounds the length of any portmantout (see
+//! `main_greedy` for the starticle).
+//!
+//! With `--assignment`, solves the assignment (minimum-cost cycle cover) relaxation over a
+//! particle set instead, where joining two particles costs minus their overlap or plus the
+//! padding between them. A dummy node stands in for the two ends of the portmantout, and only
+//! it may precede the starticle. Since paddings are only bounded from below
+//! (see `carrycoat::particle_graph`), the result is a lower bound on the length of any
+//! portmantout that `search` could produce from the same particles and padding words; pass
+//! the same `--padding-max-len N` as to `search`.
+//!
+//! With `--held-karp`, computes a Lagrangian bound over the same particle set instead. Every
+//! portmantout is a spanning arborescence rooted at the starticle in which no particle has more
+//! than one outgoing edge. We relax that last condition with a penalty per particle, compute
+//! minimum arborescences (Chu-Liu/Edmonds), and adjust the penalties by subgradient
+//! optimization. The penalties start from the duals of the assignment relaxation, so the bound
+//! is never weaker than `--assignment`'s, and we report the better of the two. Stops after
+//! `--iterations N` (default 1000) or `--time-limit SECONDS`; `--upper-bound N` (e.g. the length
+//! of the best known portmantout) is the target of the subgradient steps.
+//!
+//! With `--assignment` or `--held-karp`, `--cost MODEL` bounds the cost of a portmantout under
+//! that model (see `carrycoat::cost`) instead of its length.
+//!
+//! In every mode, `--upper-bound N` also reports the gap, and `--summary FILE` appends the bound
+//! to FILE as a line of JSON.
+
 extern crate radix_trie;
+extern crate carrycoat;
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+
+use carrycoat::cost::{self, CostModel};
+use carrycoat::flow::MinCostFlow;
+use carrycoat::joiners;
+use carrycoat::json_string;
+use carrycoat::particle_graph::ParticleGraph;
 
 #[derive(PartialEq, Eq, Debug)]
 pub struct BytesTrieKey(Vec<u8>);
@@ -9,32 +50,538 @@ impl ::radix_trie::TrieKey for BytesTrieKey {
     }
 }
 
-pub type Trie = ::radix_trie::Trie<BytesTrieKey, Option<usize>>;
+pub type Trie = ::radix_trie::Trie<BytesTrieKey, usize>;
 
 struct Cycle {
     particle: Vec<u8>,
     overlap: usize,
 }
 
-fn main_result() -> ::std::result::Result<(), Box<::std::error::Error>> {
+/// The result of one of the modes, for `--summary`.
+struct Summary {
+    mode: &'static str,
+    lower_bound: i64,
+    details: Vec<(&'static str, i64)>,
+}
+
+impl Summary {
+    /// Appends a line of JSON to `path`, so that bounds can be tracked against `search` results.
+    fn write(&self, path: &str, input: &str, upper_bound: Option<f64>) -> ::std::io::Result<()> {
+        use std::io::Write;
+        let timestamp = ::std::time::SystemTime::now().duration_since(::std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as f64 + d.subsec_nanos() as f64 * 1e-9)
+            .unwrap_or(0.0);
+        let mut line = format!("{{\"timestamp\":{:.3},\"mode\":{},\"input\":{},\"lower_bound\":{}",
+                               timestamp, json_string(self.mode.as_bytes()), json_string(input.as_bytes()),
+                               self.lower_bound);
+        for &(name, value) in &self.details {
+            line.push_str(&format!(",{}:{}", json_string(name.as_bytes()), value));
+        }
+        if let Some(upper_bound) = upper_bound {
+            let upper_bound = upper_bound as i64;
+            line.push_str(&format!(",\"upper_bound\":{},\"gap\":{},\"gap_fraction\":{:.6}",
+                                   upper_bound, upper_bound - self.lower_bound,
+                                   (upper_bound - self.lower_bound) as f64 / upper_bound as f64));
+        }
+        line.push('}');
+        let mut out = ::std::fs::OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(out, "{}", line)
+    }
+}
+
+struct AssignmentBound {
+    cost: i64,
+    overlap: i64,
+    padding: i64,
+    // Penalties for `held_karp_bound` that make its first arborescence bound at least `cost`.
+    penalty: Vec<f64>,
+}
+
+/// The minimum-cost cycle cover of the particles and a dummy node. Only the dummy may precede
+/// the starticle; with no starticle, the dummy may precede any particle.
+fn assignment_bound(graph: &ParticleGraph, starticle_idx: Option<usize>) -> AssignmentBound {
+    let n = graph.particles.len();
+    let source = 0;
+    let sink = 1;
+    let row = |idx: usize| 2 + idx;
+    let col = |idx: usize| 3 + n + idx;
+    let dummy = n;
+    let mut flow = MinCostFlow::new(4 + 2 * n);
+
+    for idx in 0..(n + 1) {
+        flow.add_edge(source, row(idx), 1, 0);
+        flow.add_edge(col(idx), sink, 1, 0);
+    }
+    match starticle_idx {
+        Some(starticle_idx) => {
+            flow.add_edge(row(dummy), col(starticle_idx), 1, 0);
+        }
+        None => {
+            for idx in 0..n {
+                flow.add_edge(row(dummy), col(idx), 1, 0);
+            }
+        }
+    }
+    for idx in 0..n {
+        flow.add_edge(row(idx), col(dummy), 1, 0);
+    }
+
+    // (edge id, overlap, cost). Rows and columns already carry at most one unit each, so the
+    // edges through groups need no capacity of their own. Without one, the potentials give
+    // every particle pair `potential(col j) <= potential(row i) + cost`.
+    let unlimited = n as i64 + 1;
+    let mut group_edges = Vec::new();
+    for group in &graph.groups {
+        let group_node = flow.add_node();
+        for &idx in &group.sources {
+            flow.add_edge(row(idx), group_node, unlimited, 0);
+        }
+        for &(idx, cost) in &group.targets {
+            if Some(idx) != starticle_idx {
+                let edge = flow.add_edge(group_node, col(idx), unlimited, cost);
+                group_edges.push((edge, group.overlap as i64, cost));
+            }
+        }
+    }
+
+    let (total_flow, cost) = flow.solve(source, sink, n as i64 + 1);
+    assert_eq!(total_flow, n as i64 + 1, "no cycle cover?");
+
+    // Charging each particle its row's potential for going anywhere, and crediting each column's
+    // potential, reproduces the assignment cost when every particle takes its cheapest in-edge.
+    // The path end has the least row potential; it pays nothing.
+    let penalty = (0..n).map(|idx| (flow.potential(row(idx)) - flow.potential(col(dummy))).max(0) as f64).collect();
+    let mut result = AssignmentBound { cost, overlap: 0, padding: 0, penalty };
+    for (edge, overlap, cost) in group_edges {
+        let f = flow.flow(edge);
+        result.overlap += f * overlap;
+        if overlap == 0 {
+            result.padding += f * cost;
+        }
+    }
+    result
+}
+
+#[derive(Clone, Copy)]
+struct InEdge {
+    source: usize,
+    target: usize,
+    cost: f64,
+}
+
+/// Heap key for f64 costs, which are never NaN here.
+#[derive(PartialEq)]
+struct Key(f64);
+
+impl Eq for Key {}
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Key) -> Option<::std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Key {
+    fn cmp(&self, other: &Key) -> ::std::cmp::Ordering {
+        self.0.partial_cmp(&other.0).unwrap()
+    }
+}
+
+/// A set of particles that the arborescence algorithm treats as a single node.
+#[derive(Default)]
+struct Component {
+    // Added to every cost below.
+    offset: f64,
+    // For each group that reaches into the component: the cheapest (cost, target) inside it,
+    // and how many of the group's sorted sources are known to be inside the component.
+    groups: HashMap<usize, (f64, usize, usize)>,
+    heap: BinaryHeap<(Reverse<Key>, usize)>,
+    // Position in the component tree that we use to recover the edges.
+    tree_id: usize,
+}
+
+fn find(parent: &mut [usize], mut idx: usize) -> usize {
+    while parent[idx] != idx {
+        parent[idx] = parent[parent[idx]];
+        idx = parent[idx];
+    }
+    idx
+}
+
+/// Minimum spanning arborescence rooted at `root`, where the edge from `i` to `j` costs
+/// `penalty[i]` plus the cheapest group cost. Returns the in-edge of every other particle.
+/// `sorted_sources[g]` lists the sources of group `g` by increasing penalty.
+///
+/// This is Tarjan's version of Chu-Liu/Edmonds: grow a path backwards along cheapest in-edges
+/// and contract cycles as they close, so each component's in-edges are searched only once.
+fn min_arborescence(in_groups: &[Vec<(usize, i64)>], sorted_sources: &[Vec<usize>],
+                    penalty: &[f64], root: usize) -> Vec<InEdge> {
+    let n = in_groups.len();
+    let mut parent: Vec<usize> = (0..n).collect();
+    let mut components: Vec<Component> = (0..n).map(|target| {
+        let mut component = Component { tree_id: target, ..Default::default() };
+        for &(group_idx, cost) in &in_groups[target] {
+            component.groups.insert(group_idx, (cost as f64, target, 0));
+            component.heap.push((Reverse(Key(cost as f64 + penalty[sorted_sources[group_idx][0]])), group_idx));
+        }
+        component
+    }).collect();
+
+    // Component tree: every contracted cycle becomes a node whose children are the
+    // components on the cycle, each with the edge that entered it on the cycle.
+    let mut tree_children: Vec<Vec<(usize, InEdge)>> = vec![Vec::new(); n];
+    let mut tree_parent: Vec<usize> = (0..n).collect();
+    let mut chosen: Vec<Option<InEdge>> = vec![None; n];
+
+    let mut seen = vec![usize::MAX; n];
+    seen[root] = root;
+    for start in 0..n {
+        let mut path = Vec::new();
+        let mut current = find(&mut parent, start);
+        while seen[current] == usize::MAX {
+            seen[current] = start;
+            path.push(current);
+
+            // Cheapest edge into the component from outside it.
+            let edge = loop {
+                let component = &mut components[current];
+                let (Reverse(Key(key)), group_idx) = component.heap.pop().expect("particle with no way in");
+                let (cost, target, mut position) = component.groups[&group_idx];
+                let sources = &sorted_sources[group_idx];
+                while position < sources.len() && find(&mut parent, sources[position]) == current {
+                    position += 1;
+                }
+                let component = &mut components[current];
+                component.groups.insert(group_idx, (cost, target, position));
+                if position == sources.len() {
+                    continue;
+                }
+                let actual = cost + penalty[sources[position]];
+                if actual > key {
+                    // Stale: the cheapest source joined this component.
+                    component.heap.push((Reverse(Key(actual)), group_idx));
+                    continue;
+                }
+                if actual < key {
+                    // Stale: a cheaper target was merged in, and has its own entry.
+                    continue;
+                }
+                component.heap.push((Reverse(Key(actual)), group_idx));
+                let reduced = actual + component.offset;
+                component.offset -= reduced;
+                // Recover the plain cost from the group.
+                let group_cost = in_groups[target].iter().find(|&&(g, _)| g == group_idx).unwrap().1;
+                break InEdge { source: sources[position], target, cost: group_cost as f64 };
+            };
+            let tree_id = components[current].tree_id;
+            chosen[tree_id] = Some(edge);
+
+            let next = find(&mut parent, edge.source);
+            if seen[next] != start {
+                current = next;
+                continue;
+            }
+
+            // Contract the cycle, merging smaller components into larger ones.
+            let cycle_start = path.iter().position(|&c| c == next).unwrap();
+            let cycle: Vec<usize> = path.drain(cycle_start..).collect();
+            let new_id = tree_children.len();
+            tree_children.push(cycle.iter().map(|&c| {
+                let id = components[c].tree_id;
+                (id, chosen[id].unwrap())
+            }).collect());
+            tree_parent.push(new_id);
+            chosen.push(None);
+            for &c in &cycle {
+                tree_parent[components[c].tree_id] = new_id;
+            }
+            let mut merged = cycle[0];
+            for &c in &cycle[1..] {
+                let (big, small) = if components[c].groups.len() > components[merged].groups.len() {
+                    (c, merged)
+                } else {
+                    (merged, c)
+                };
+                parent[small] = big;
+                let small = ::std::mem::take(&mut components[small]);
+                let big_component = &mut components[big];
+                let shift = small.offset - big_component.offset;
+                for (group_idx, (cost, target, position)) in small.groups {
+                    let cost = cost + shift;
+                    let entry = big_component.groups.entry(group_idx).or_insert((f64::INFINITY, target, 0));
+                    entry.2 = ::std::cmp::max(entry.2, position);
+                    // Entries whose source has moved are fixed up when they reach the top.
+                    if cost < entry.0 && entry.2 < sorted_sources[group_idx].len() {
+                        entry.0 = cost;
+                        entry.1 = target;
+                        let key = cost + penalty[sorted_sources[group_idx][entry.2]];
+                        big_component.heap.push((Reverse(Key(key)), group_idx));
+                    }
+                }
+                merged = big;
+            }
+            let component = &mut components[merged];
+            component.tree_id = new_id;
+            seen[merged] = usize::MAX;
+            current = merged;
+        }
+    }
+
+    // Expand the component tree to get the edge into every particle.
+    let mut result = Vec::new();
+    let mut stack = Vec::new();
+    for (idx, component) in components.iter().enumerate() {
+        if idx != root && parent[idx] == idx {
+            stack.push((component.tree_id, chosen[component.tree_id].unwrap()));
+        }
+    }
+    while let Some((id, edge)) = stack.pop() {
+        // The edge enters every component from its target up to `id`. The other components
+        // on those cycles keep the edges they had there.
+        result.push(edge);
+        let mut entered = edge.target;
+        while entered != id {
+            let up = tree_parent[entered];
+            for &(child, child_edge) in &tree_children[up] {
+                if child != entered {
+                    stack.push((child, child_edge));
+                }
+            }
+            entered = up;
+        }
+    }
+    result
+}
+
+/// Starts from `penalty` (e.g. from `assignment_bound`) and takes Polyak steps toward
+/// `upper_bound`, scaled by a factor that halves whenever the bound stalls. Returns the best
+/// bound found.
+fn held_karp_bound(graph: &ParticleGraph, root: usize, mut penalty: Vec<f64>, max_iterations: usize,
+                   time_limit: Option<::std::time::Duration>, upper_bound: Option<f64>) -> f64 {
+    // Iterations without improvement before the step scale halves.
+    const STALL: usize = 20;
+
+    let n = graph.particles.len();
+    let total_len = graph.total_len() as f64;
+    let started = ::std::time::Instant::now();
+
+    let mut in_groups = vec![Vec::new(); n];
+    for (group_idx, group) in graph.groups.iter().enumerate() {
+        for &(target, cost) in &group.targets {
+            in_groups[target].push((group_idx, cost));
+        }
+    }
+
+    let mut best_bound = f64::NEG_INFINITY;
+    let mut best_penalty = penalty.clone();
+    let mut step_scale = 2.0;
+    let mut since_improvement = 0;
+    for iteration in 0..max_iterations {
+        if time_limit.is_some_and(|limit| started.elapsed() >= limit) {
+            println!("reached time limit");
+            break;
+        }
+        let sorted_sources: Vec<Vec<usize>> = graph.groups.iter().map(|group| {
+            let mut sources = group.sources.clone();
+            sources.sort_by(|&a, &b| penalty[a].partial_cmp(&penalty[b]).unwrap());
+            sources
+        }).collect();
+
+        let edges = min_arborescence(&in_groups, &sorted_sources, &penalty, root);
+        let mut out_degree = vec![0i64; n];
+        let mut cost = 0.0;
+        for edge in &edges {
+            out_degree[edge.source] += 1;
+            cost += edge.cost + penalty[edge.source];
+        }
+        let bound = total_len + cost - penalty.iter().sum::<f64>();
+        println!("iteration {}: bound {:.1} (best {}), branching particles: {}, step scale {}",
+                 iteration, bound, (bound.max(best_bound) - 1e-6).ceil(),
+                 out_degree.iter().filter(|&&d| d > 1).count(), step_scale);
+        if bound > best_bound + 1e-6 {
+            best_bound = bound;
+            best_penalty = penalty.clone();
+            since_improvement = 0;
+        } else {
+            since_improvement += 1;
+            if since_improvement >= STALL {
+                // Go back to the best penalties, and take smaller steps from there.
+                step_scale /= 2.0;
+                since_improvement = 0;
+                penalty = best_penalty.clone();
+                continue;
+            }
+        }
+
+        // Subgradient for the relaxed out-degree limits, keeping the penalties nonnegative.
+        let subgradient: Vec<f64> = (0..n).map(|idx| {
+            let g = (out_degree[idx] - 1) as f64;
+            if penalty[idx] <= 0.0 && g < 0.0 { 0.0 } else { g }
+        }).collect();
+        let norm: f64 = subgradient.iter().map(|g| g * g).sum();
+        if norm == 0.0 {
+            println!("arborescence is a path");
+            break;
+        }
+        let target = upper_bound.unwrap_or(best_bound + 0.01 * best_bound.abs());
+        let step = step_scale * (target - bound).max(1.0) / norm;
+        for (p, g) in penalty.iter_mut().zip(&subgradient) {
+            *p = (*p + step * g).max(0.0);
+        }
+    }
+    best_bound
+}
+
+fn load_particle_graph(particles_path: &str, joiners_path: &str, wordlist_path: &str, padding_max_len: usize,
+                       model: &dyn CostModel)
+                       -> ::std::result::Result<(ParticleGraph, usize), Box<dyn (::std::error::Error)>>
+{
     use std::io::{BufRead};
 
-    let args : Vec<String> = ::std::env::args().collect();
-    if args.len() != 2 {
-        println!("usage: {} REDUCED_WORDLIST_FILE", args[0]);
+    let mut particles = Vec::new();
+    for maybe_word in ::std::io::BufReader::new(::std::fs::File::open(particles_path)?).split(b'\n') {
+        particles.push(maybe_word?);
+    }
+    let starticle_idx = particles.iter().position(|p| p.starts_with(b"portmanteau"))
+        .ok_or("no particle starts with 'portmanteau'")?;
+
+    // The same vocabulary that join and search pad with.
+    let (_, padding_words) = joiners::load(joiners_path, wordlist_path, padding_max_len)?;
+    let words = padding_words.words();
+
+    println!("particle count: {}", particles.len());
+    let graph = ParticleGraph::new(particles, &words, model);
+    println!("group count: {}", graph.groups.len());
+    Ok((graph, starticle_idx))
+}
+
+fn main_assignment(graph: &ParticleGraph, starticle_idx: usize) -> Summary {
+    let result = assignment_bound(graph, Some(starticle_idx));
+    let lower_bound = graph.total_len() as i64 + result.cost;
+    println!("total particle length: {}", graph.total_len());
+    println!("assignment overlap: {}, padding at least: {}", result.overlap, result.padding);
+    println!("lower bound: {}", lower_bound);
+    Summary {
+        mode: "assignment",
+        lower_bound,
+        details: vec![
+            ("particles", graph.particles.len() as i64),
+            ("total_chars", graph.total_len() as i64),
+            ("overlap", result.overlap),
+            ("padding", result.padding),
+        ],
+    }
+}
+
+fn main_held_karp(graph: &ParticleGraph, starticle_idx: usize, max_iterations: usize,
+                  time_limit: Option<::std::time::Duration>, upper_bound: Option<f64>) -> Summary {
+    let assignment = assignment_bound(graph, Some(starticle_idx));
+    let assignment_lower_bound = graph.total_len() as i64 + assignment.cost;
+    println!("assignment bound: {}", assignment_lower_bound);
+    // The costs are integers, so we can round up, but not over a rounding error above one.
+    let bound = held_karp_bound(graph, starticle_idx, assignment.penalty, max_iterations, time_limit, upper_bound);
+    let held_karp_lower_bound = (bound - 1e-6).ceil() as i64;
+    let lower_bound = ::std::cmp::max(held_karp_lower_bound, assignment_lower_bound);
+    println!("lower bound: {}", lower_bound);
+    Summary {
+        mode: "held_karp",
+        lower_bound,
+        details: vec![
+            ("particles", graph.particles.len() as i64),
+            ("total_chars", graph.total_len() as i64),
+            ("assignment", assignment_lower_bound),
+        ],
+    }
+}
+
+fn main_result() -> ::std::result::Result<(), Box<::std::error::Error>> {
+    let mut assignment = false;
+    let mut held_karp = false;
+    let mut max_iterations = 1000;
+    let mut time_limit = None;
+    let mut upper_bound = None;
+    let mut summary_path = None;
+    let mut cost_spec = None;
+    let mut padding_max_len = None;
+    let mut args = Vec::new();
+    let mut arg_iter = ::std::env::args();
+    while let Some(arg) = arg_iter.next() {
+        match &arg[..] {
+            "--assignment" => assignment = true,
+            "--held-karp" => held_karp = true,
+            "--iterations" => {
+                max_iterations = arg_iter.next().ok_or("--iterations requires N")?.parse()?;
+            }
+            "--time-limit" => {
+                let seconds = arg_iter.next().ok_or("--time-limit requires SECONDS")?.parse()?;
+                time_limit = Some(::std::time::Duration::from_secs(seconds));
+            }
+            "--upper-bound" => {
+                upper_bound = Some(arg_iter.next().ok_or("--upper-bound requires N")?.parse()?);
+            }
+            "--summary" => summary_path = Some(arg_iter.next().ok_or("--summary requires FILE")?),
+            "--cost" => cost_spec = Some(arg_iter.next().ok_or("--cost requires MODEL")?),
+            "--padding-max-len" => {
+                padding_max_len = Some(arg_iter.next().ok_or("--padding-max-len requires N")?.parse()?);
+            }
+            _ => args.push(arg),
+        }
+    }
+    let model = match cost_spec {
+        Some(ref spec) => cost::parse(spec)?,
+        None => Box::new(cost::Length),
+    };
+    let summary = if (assignment || held_karp) && args.len() == 4 {
+        let max_len = padding_max_len.unwrap_or(joiners::DEFAULT_PADDING_MAX_LEN);
+        let (graph, starticle_idx) = load_particle_graph(&args[1], &args[2], &args[3], max_len, &*model)?;
+        if assignment {
+            main_assignment(&graph, starticle_idx)
+        } else {
+            main_held_karp(&graph, starticle_idx, max_iterations, time_limit, upper_bound)
+        }
+    } else if !assignment && !held_karp && cost_spec.is_none() && padding_max_len.is_none() && args.len() == 2 {
+        main_greedy(load_words(&args[1])?)
+    } else {
+        println!("usage: {} [--summary FILE] [--upper-bound N] REDUCED_WORDLIST_FILE", args[0]);
+        println!("       {} --assignment [--cost MODEL] [--padding-max-len N] [--summary FILE] [--upper-bound N] \
+                  PARTICLES_FILE JOINERS_FILE WORDLIST_FILE", args[0]);
+        println!("       {} --held-karp [--cost MODEL] [--padding-max-len N] [--iterations N] [--time-limit SECONDS] [--upper-bound N] [--summary FILE] \
+                  PARTICLES_FILE JOINERS_FILE WORDLIST_FILE", args[0]);
         return Ok(());
+    };
+    if let Some(upper_bound) = upper_bound {
+        println!("gap to upper bound {}: {}", upper_bound, upper_bound as i64 - summary.lower_bound);
     }
+    if let Some(path) = summary_path {
+        summary.write(&path, &args[1], upper_bound)?;
+    }
+    Ok(())
+}
+
+fn load_words(wordlist_path: &str) -> ::std::result::Result<Vec<Vec<u8>>, Box<dyn (::std::error::Error)>> {
+    use std::io::{BufRead};
+
+    let mut words = Vec::new();
+    for maybe_word in ::std::io::BufReader::new(::std::fs::File::open(wordlist_path)?).split(b'\n') {
+        words.push(maybe_word?);
+    }
+    Ok(words)
+}
+
+/// Merges the words that overlap the most until nothing overlaps. A word that overlaps only
+/// itself closes a cycle. Returns the cycles and the words that are left, each with the total
+/// overlap inside it.
+fn greedy_cycles(words: &[Vec<u8>]) -> (Vec<Cycle>, Vec<Cycle>) {
+    // No overlap can be longer than the longest word.
+    let mut overlap_upper_bound = words.iter().map(|w| w.len()).max().unwrap_or(0);
 
     let mut trie = Trie::new();
-    for maybe_word in ::std::io::BufReader::new(try!(::std::fs::File::open(&args[1]))).split('\n' as u8) {
-        let word = try!(maybe_word);
-        trie.insert(BytesTrieKey(word), None);
+    for word in words {
+        trie.insert(BytesTrieKey(word.clone()), 0);
     }
 
     let mut cycles = Vec::new();
 
-    let mut overlap_upper_bound = 16; // differentiation has 15 letters
-
     // Let's just find the biggest overlap.
     'outer: loop {
         let mut most_overlap = 0;
@@ -65,52 +612,101 @@ fn main_result() -> ::std::result::Result<(), Box<::std::error::Error>> {
             break 'outer;
         }
 
-        trie.remove(&BytesTrieKey(best_word.clone()));
-
+        // Look for another word to merge with before we take this one out of the trie. If the
+        // only match is the word itself, it closes a cycle.
         let overlap = &best_word[(best_word.len() - most_overlap)..];
-
         let key = BytesTrieKey(overlap.to_vec());
-        let to_remove_key = BytesTrieKey(
-            trie.get_descendant(&key).expect("broken trie?").keys().next().expect("no key?").0.clone());
-
-        trie.remove(&to_remove_key);
-        let trie_word = to_remove_key.0;
-
-        if trie_word == best_word {
-            println!("new cycle with overlap {}: {:?}", most_overlap, trie_word);
-            cycles.push(Cycle { particle: trie_word.clone(), overlap: most_overlap});
-        } else {
+        let trie_word = trie.get_descendant(&key).expect("broken trie?").keys()
+            .map(|key| &key.0).find(|&word| *word != best_word).cloned();
+        let best_overlap = trie.remove(&BytesTrieKey(best_word.clone())).expect("no best word?");
 
-
-            let mut new_word = best_word.clone();
-            for idx in most_overlap .. trie_word.len() {
-                new_word.push(trie_word[idx]);
+        match trie_word {
+            None => {
+                println!("new cycle with overlap {}: {:?}", most_overlap, best_word);
+                cycles.push(Cycle { particle: best_word, overlap: best_overlap + most_overlap });
             }
+            Some(trie_word) => {
+                let trie_overlap = trie.remove(&BytesTrieKey(trie_word.clone())).expect("no trie word?");
+                let mut new_word = best_word.clone();
+                new_word.extend_from_slice(&trie_word[most_overlap..]);
 
-            println!("new_word = {:?}", ::std::str::from_utf8(&new_word));
-            let new_key = BytesTrieKey(new_word);
-            trie.insert(new_key, None);
+                println!("new_word = {:?}", ::std::str::from_utf8(&new_word));
+                trie.insert(BytesTrieKey(new_word), best_overlap + trie_overlap + most_overlap);
+            }
         }
 
         overlap_upper_bound = most_overlap;
 
-//        assert_eq!(word_set.len(), trie.len());
         println!("particle count: {}", trie.len());
         println!("cycle count: {}", cycles.len());
     }
 
+    let paths = trie.iter().map(|(key, &overlap)| Cycle { particle: key.0.clone(), overlap }).collect();
+    (cycles, paths)
+}
+
+fn main_greedy(words: Vec<Vec<u8>>) -> Summary {
+    let word_count = words.len();
+    let total_chars: usize = words.iter().map(|w| w.len()).sum();
+
+    // A portmantout is a path, which becomes a cycle if we add an edge from its last word back
+    // to its first. Nothing overlaps into the first word, so we mark the words that could come
+    // first to keep anything from overlapping into them. All but one of them do have a
+    // predecessor, which we make up for with the most they could possibly overlap with it.
+    let starticles: Vec<Vec<u8>> = words.iter().filter(|w| w.starts_with(b"portmanteau")).cloned().collect();
+    let mut starticle_overlaps: Vec<usize> = starticles.iter().map(|starticle| {
+        words.iter().filter(|w| *w != starticle).filter_map(|w| {
+            (1..::std::cmp::min(w.len(), starticle.len())).rev().find(|&len| w.ends_with(&starticle[..len]))
+        }).max().unwrap_or(0)
+    }).collect();
+    starticle_overlaps.sort();
+    let starticle_adjustment: usize = starticle_overlaps.iter().skip(1).sum();
 
+    let marked: Vec<Vec<u8>> = words.into_iter().map(|mut word| {
+        if word.starts_with(b"portmanteau") {
+            word.insert(0, b'^');
+        }
+        word
+    }).collect();
+
+    let (cycles, paths) = greedy_cycles(&marked);
     println!("OUTPUT CYCLES ---- :");
     for idx in 0..cycles.len() {
         println!("new cycle with overlap {}: {:?}", cycles[idx].overlap, cycles[idx].particle);
     }
     println!("OUTPUT NONCYLCES -----");
-    for key in trie.keys() {
-        let word = &key.0;
+    for path in &paths {
+        let word = path.particle.strip_prefix(b"^").unwrap_or(&path.particle);
         println!("{}", ::std::str::from_utf8(word).unwrap());
     }
+    let greedy_overlap: usize = cycles.iter().chain(&paths).map(|c| c.overlap).sum();
+
+    // Greedy merging need not find a cycle cover of maximum overlap, so the bound comes from
+    // the assignment problem over the same words. Nothing overlaps into a marked word, so it
+    // does not matter which of them the dummy node precedes.
+    let graph = ParticleGraph::new(marked, &[], &cost::Length);
+    let starticle_idx = graph.particles.iter().position(|w| w.starts_with(b"^"));
+    let result = assignment_bound(&graph, starticle_idx);
 
-    return Ok(());
+    let lower_bound = total_chars as i64 + result.cost - starticle_adjustment as i64;
+    println!("total characters: {}", total_chars);
+    println!("greedy cycle overlap: {} ({} cycles)", greedy_overlap, cycles.len());
+    println!("cycle cover overlap: {}", result.overlap);
+    println!("starticle candidates: {}, overlap into those that don't start: at most {}",
+             starticles.len(), starticle_adjustment);
+    println!("lower bound: {}", lower_bound);
+    Summary {
+        mode: "greedy",
+        lower_bound,
+        details: vec![
+            ("words", word_count as i64),
+            ("total_chars", total_chars as i64),
+            ("overlap", result.overlap),
+            ("greedy_overlap", greedy_overlap as i64),
+            ("cycles", cycles.len() as i64),
+            ("starticle_adjustment", starticle_adjustment as i64),
+        ],
+    }
 }
 
 pub fn main() {
@@ -121,3 +717,118 @@ pub fn main() {
         }
     }
 }
+
+#[test]
+fn test_min_arborescence() {
+    // Four particles in three groups; compare with every possible choice of parents.
+    let sources = [vec![0, 2], vec![1, 3], vec![0, 1, 2, 3]];
+    let mut in_groups = vec![Vec::new(); 4];
+    for &(group_idx, target, cost) in &[(0, 1, -3), (0, 3, -1), (1, 2, -2), (1, 0, -2), (1, 3, -2),
+                                        (2, 0, 1), (2, 1, 2), (2, 2, 1), (2, 3, 3)] {
+        in_groups[target].push((group_idx, cost));
+    }
+    for penalty in &[[0.0, 0.0, 0.0, 0.0], [0.0, 1.5, 0.5, 0.0], [2.0, 0.0, 3.0, 1.0], [0.0, 4.0, 0.0, 4.0]] {
+        let sorted_sources: Vec<Vec<usize>> = sources.iter().map(|group_sources| {
+            let mut sorted = group_sources.clone();
+            sorted.sort_by(|&a, &b| penalty[a].partial_cmp(&penalty[b]).unwrap());
+            sorted
+        }).collect();
+        let edge_cost = |source: usize, target: usize| {
+            in_groups[target].iter()
+                .filter(|&&(group_idx, _)| sources[group_idx].contains(&source))
+                .map(|&(_, cost)| cost as f64 + penalty[source])
+                .fold(f64::INFINITY, f64::min)
+        };
+
+        let mut best = f64::INFINITY;
+        for choice in 0..64 {
+            let parents = [0, choice % 4, choice / 4 % 4, choice / 16];
+            let reaches_root = |mut idx: usize| (0..4).any(|_| { idx = parents[idx]; idx == 0 });
+            if (1..4).all(|idx| parents[idx] != idx && reaches_root(idx)) {
+                best = best.min((1..4).map(|idx| edge_cost(parents[idx], idx)).sum());
+            }
+        }
+
+        let edges = min_arborescence(&in_groups, &sorted_sources, penalty, 0);
+        assert_eq!(edges.len(), 3);
+        let total: f64 = edges.iter().map(|edge| edge.cost + penalty[edge.source]).sum();
+        assert_eq!(total, best);
+    }
+}
+
+
+#[test]
+fn test_assignment_bound() {
+    // Compare with every cycle cover in which only the dummy precedes the starticle.
+    let particles: Vec<Vec<u8>> = vec![b"abcd".to_vec(), b"cdef".to_vec(), b"efab".to_vec(), b"xyzc".to_vec()];
+    let words = vec![b"dqx".to_vec(), b"fzzx".to_vec(), b"bx".to_vec()];
+    let graph = ParticleGraph::new(particles, &words, &cost::Length);
+    let n = graph.particles.len();
+    let edge_cost = |source: usize, target: usize| {
+        if target == n {
+            return 0;
+        }
+        graph.groups.iter()
+            .filter(|group| group.sources.contains(&source))
+            .filter_map(|group| group.targets.iter().find(|&&(idx, _)| idx == target).map(|&(_, cost)| cost))
+            .min().unwrap()
+    };
+
+    for starticle_idx in 0..n {
+        let mut best = i64::MAX;
+        for choice in 0..(n + 1).pow(n as u32) {
+            let mut next: Vec<usize> = (0..n).map(|idx| choice / (n + 1).pow(idx as u32) % (n + 1)).collect();
+            next.push(starticle_idx);
+            let mut used = vec![false; n + 1];
+            for &idx in &next {
+                used[idx] = true;
+            }
+            if used.iter().all(|&u| u) && !next[..n].contains(&starticle_idx) {
+                best = ::std::cmp::min(best, (0..n).map(|idx| edge_cost(idx, next[idx])).sum());
+            }
+        }
+        assert_eq!(assignment_bound(&graph, Some(starticle_idx)).cost, best);
+    }
+}
+
+#[test]
+fn test_greedy() {
+    // ab and ba merge into aba, which then closes a cycle by overlapping itself.
+    let words = vec![b"ab".to_vec(), b"ba".to_vec()];
+    let (cycles, paths) = greedy_cycles(&words);
+    assert_eq!(cycles.len(), 1);
+    assert_eq!(cycles[0].particle, b"aba".to_vec());
+    assert_eq!(cycles[0].overlap, 2);
+    assert!(paths.is_empty());
+
+    // But a portmantout is a path, so the bound counts only one of the overlaps.
+    assert_eq!(main_greedy(words).lower_bound, 3);
+
+    // xyz overlaps nothing, so it is left over.
+    let (cycles, paths) = greedy_cycles(&[b"abc".to_vec(), b"cab".to_vec(), b"xyz".to_vec()]);
+    assert_eq!(cycles.len(), 1);
+    assert_eq!(paths.len(), 1);
+    assert_eq!(cycles[0].overlap + paths[0].overlap, 3);
+}
+
+#[test]
+fn test_held_karp_bound() {
+    // The penalties from the assignment bound make the first arborescence at least as good.
+    let particles: Vec<Vec<u8>> = vec![b"abcd".to_vec(), b"cdef".to_vec(), b"efab".to_vec(), b"xyzc".to_vec()];
+    let words = vec![b"dqx".to_vec(), b"fzzx".to_vec(), b"bx".to_vec()];
+    let graph = ParticleGraph::new(particles, &words, &cost::Length);
+    for starticle_idx in 0..graph.particles.len() {
+        let assignment = assignment_bound(&graph, Some(starticle_idx));
+        let bound = held_karp_bound(&graph, starticle_idx, assignment.penalty, 1, None, None);
+        assert!(bound >= (graph.total_len() as i64 + assignment.cost) as f64 - 1e-6);
+    }
+
+    // The assignment can cycle through abcd and cdab, but a portmantout has to get there from
+    // the starticle.
+    let particles: Vec<Vec<u8>> = vec![b"wxyz".to_vec(), b"abcd".to_vec(), b"cdab".to_vec()];
+    let graph = ParticleGraph::new(particles, &words, &cost::Length);
+    let assignment = assignment_bound(&graph, Some(0));
+    let assignment_lower_bound = graph.total_len() as i64 + assignment.cost;
+    let bound = held_karp_bound(&graph, 0, assignment.penalty, 100, None, Some(12.0));
+    assert!(bound > assignment_lower_bound as f64 + 1.0, "{} vs {}", bound, assignment_lower_bound);
+}
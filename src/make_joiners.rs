@@ -0,0 +1,212 @@
+//! Receives an (ascii, newline-delimited) wordlist on stdin. Writes a joiner table to stdout:
+//! for each pair of letters, a string that starts with the first and ends with the second, and
+//! in which every two adjacent letters are covered by a word. That is the shortest word with
+//! those ends if there is one, and otherwise the shortest chain of overlapping words. With
+//! `--shortest`, chains win whenever they are strictly shorter than the shortest word.
+//!
+//! Reports to stderr which pairs needed chains and which have no joiner at all.
+
+extern crate carrycoat;
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, HashMap};
+
+/// Overlapping words, as a graph. A node is a string that ends some word and starts another,
+/// where the next word can overlap the current one.
+struct Chains<'a> {
+    words: &'a [Vec<u8>],
+    node_ids: HashMap<&'a [u8], usize>,
+    nodes: Vec<&'a [u8]>,
+    // The words that start with each node (and are longer than it).
+    words_after: Vec<Vec<usize>>,
+    // For each word, the nodes that it ends with (and that are shorter than it).
+    nodes_after: Vec<Vec<usize>>,
+}
+
+impl<'a> Chains<'a> {
+    fn new(words: &'a [Vec<u8>]) -> Chains<'a> {
+        let mut by_prefix = HashMap::<&[u8], Vec<usize>>::new();
+        for (idx, word) in words.iter().enumerate() {
+            for len in 1..word.len() {
+                by_prefix.entry(&word[..len]).or_default().push(idx);
+            }
+        }
+
+        let mut chains = Chains {
+            words,
+            node_ids: HashMap::new(),
+            nodes: Vec::new(),
+            words_after: Vec::new(),
+            nodes_after: Vec::new(),
+        };
+        for word in words {
+            let mut after = Vec::new();
+            for len in 1..word.len() {
+                let suffix = &word[word.len() - len..];
+                if by_prefix.contains_key(suffix) {
+                    after.push(chains.node(suffix, &by_prefix));
+                }
+            }
+            chains.nodes_after.push(after);
+        }
+        for word in words {
+            chains.node(&word[..1], &by_prefix);
+        }
+        chains
+    }
+
+    fn node(&mut self, chars: &'a [u8], by_prefix: &HashMap<&[u8], Vec<usize>>) -> usize {
+        if let Some(&id) = self.node_ids.get(chars) {
+            return id;
+        }
+        let id = self.nodes.len();
+        self.node_ids.insert(chars, id);
+        self.nodes.push(chars);
+        self.words_after.push(by_prefix.get(chars).cloned().unwrap_or_default());
+        id
+    }
+
+    /// The shortest chain from `first` to each letter it can reach.
+    fn shortest_from(&self, first: u8) -> BTreeMap<u8, Vec<u8>> {
+        let start = match self.node_ids.get(&[first][..]) {
+            Some(&id) => id,
+            None => return BTreeMap::new(),
+        };
+        let mut dist = vec![usize::MAX; self.nodes.len()];
+        let mut pred: Vec<Option<(usize, usize)>> = vec![None; self.nodes.len()];
+        // For each last letter: (length, node, word).
+        let mut ends = BTreeMap::<u8, (usize, usize, usize)>::new();
+
+        dist[start] = 1;
+        let mut heap = BinaryHeap::new();
+        heap.push(Reverse((1, start)));
+        while let Some(Reverse((d, node))) = heap.pop() {
+            if d > dist[node] {
+                continue;
+            }
+            let overlap = self.nodes[node].len();
+            for &word_idx in &self.words_after[node] {
+                let word = &self.words[word_idx];
+                let len = d + word.len() - overlap;
+                let end = ends.entry(word[word.len() - 1]).or_insert((usize::MAX, 0, 0));
+                if len < end.0 {
+                    *end = (len, node, word_idx);
+                }
+                for &next in &self.nodes_after[word_idx] {
+                    if len < dist[next] {
+                        dist[next] = len;
+                        pred[next] = Some((node, word_idx));
+                        heap.push(Reverse((len, next)));
+                    }
+                }
+            }
+        }
+
+        ends.into_iter().map(|(last, (_, node, word_idx))| {
+            // Walk back to the start, collecting the parts that each word adds.
+            let mut parts = vec![&self.words[word_idx][self.nodes[node].len()..]];
+            let mut current = node;
+            while let Some((prev, prev_word_idx)) = pred[current] {
+                parts.push(&self.words[prev_word_idx][self.nodes[prev].len()..]);
+                current = prev;
+            }
+            let mut joiner = vec![first];
+            for part in parts.iter().rev() {
+                joiner.extend_from_slice(part);
+            }
+            (last, joiner)
+        }).collect()
+    }
+}
+
+fn main_result() -> ::std::result::Result<(), Box<dyn std::error::Error>> {
+    use std::io::{BufRead, Write};
+
+    let shortest = ::std::env::args().any(|arg| arg == "--shortest");
+
+    let stdin = ::std::io::stdin();
+    let mut words = BTreeSet::new();
+    for maybe_word in stdin.lock().split(b'\n') {
+        let word = maybe_word?;
+        if !word.is_empty() {
+            words.insert(word);
+        }
+    }
+    let words: Vec<Vec<u8>> = words.into_iter().collect();
+
+    let mut single = HashMap::<(u8, u8), &[u8]>::new();
+    for word in &words {
+        if word.len() < 2 {
+            continue;
+        }
+        let key = (word[0], word[word.len() - 1]);
+        if single.get(&key).is_none_or(|best| word.len() < best.len()) {
+            single.insert(key, word);
+        }
+    }
+
+    let letters: BTreeSet<u8> = words.iter().flat_map(|w| vec![w[0], w[w.len() - 1]]).collect();
+    let chains = Chains::new(&words);
+    let mut table = BTreeMap::<(u8, u8), Vec<u8>>::new(); // keyed by (last, first), for output order
+    let mut chained = Vec::new();
+    let mut impossible = Vec::new();
+    for &first in &letters {
+        let from_first = chains.shortest_from(first);
+        for &last in &letters {
+            let joiner = match (single.get(&(first, last)), from_first.get(&last)) {
+                (Some(word), Some(chain)) if shortest && chain.len() < word.len() => {
+                    chained.push((first, last));
+                    chain.clone()
+                }
+                (Some(word), _) => word.to_vec(),
+                (None, Some(chain)) => {
+                    chained.push((first, last));
+                    chain.clone()
+                }
+                (None, None) => {
+                    impossible.push((first, last));
+                    continue;
+                }
+            };
+            table.insert((last, first), joiner);
+        }
+    }
+
+    let stdout = ::std::io::stdout();
+    let mut out = ::std::io::BufWriter::new(stdout.lock());
+    for joiner in table.values() {
+        out.write_all(joiner)?;
+        out.write_all(b"\n")?;
+    }
+    out.flush()?;
+
+    eprintln!("{} joiners, {} of them chains of words", table.len(), chained.len());
+    if !chained.is_empty() {
+        eprintln!("chained: {}", ::carrycoat::joiners::format_pairs(&chained));
+    }
+    if !impossible.is_empty() {
+        eprintln!("no possible joiner: {}", ::carrycoat::joiners::format_pairs(&impossible));
+    }
+    Ok(())
+}
+
+pub fn main() {
+    match main_result() {
+        Ok(()) => {}
+        Err(e) => {
+            // Not on stdout, which is the joiner table.
+            eprintln!("error: {}", e);
+            ::std::process::exit(1);
+        }
+    }
+}
+
+#[test]
+fn test_shortest_chain() {
+    let words = vec![b"arc".to_vec(), b"bar".to_vec(), b"basic".to_vec(), b"cod".to_vec()];
+    let chains = Chains::new(&words);
+    let from_b = chains.shortest_from(b'b');
+    assert_eq!(from_b[&b'c'], b"barc".to_vec());
+    assert_eq!(from_b[&b'd'], b"barcod".to_vec());
+    assert!(!from_b.contains_key(&b'b'));
+}
@@ -0,0 +1,283 @@
+//! Writes a synthetic wordlist to stdout, for experiments with known properties. Every word
+//! is `--min-len` to `--max-len` letters (uniformly) from the first `--alphabet K` letters.
+//! `--overlap P` is the probability that a word starts with the end of an earlier word (by one
+//! to `--max-overlap` letters) rather than with random letters. `--starticle` adds a word that
+//! starts with "portmanteau", which `join` and `search` need. The same `--seed N` always gives
+//! the same wordlist.
+//!
+//! With `--planted`, the words instead form a chain in which each word overlaps the next by
+//! one to `--max-overlap` letters, and no word overlaps any other word by more than it
+//! overlaps its successor in the chain, or contains another word. Any portmantout overlaps
+//! each word with at most one other word, and one word with none, so the chain is a shortest
+//! portmantout, as long as the last word overlaps nothing by more than the least overlap in
+//! the chain (which we also make sure of). `--solution FILE` writes it.
+
+extern crate carrycoat;
+extern crate rand;
+
+use std::collections::{HashMap, HashSet};
+
+use rand::Rng;
+
+struct Options {
+    word_count: usize,
+    alphabet: u8,
+    min_len: usize,
+    max_len: usize,
+    overlap: f64,
+    max_overlap: usize,
+    starticle: bool,
+}
+
+/// The words so far, indexed for the checks that planting needs.
+#[derive(Default)]
+struct Words {
+    words: Vec<Vec<u8>>,
+    set: HashSet<Vec<u8>>,
+    // Every substring of every word.
+    substrings: HashSet<Vec<u8>>,
+    // Proper prefixes and suffixes, with the words they belong to.
+    prefixes: HashMap<Vec<u8>, Vec<usize>>,
+    suffixes: HashMap<Vec<u8>, Vec<usize>>,
+}
+
+impl Words {
+    fn push(&mut self, word: Vec<u8>) {
+        let idx = self.words.len();
+        for start in 0..word.len() {
+            for end in (start + 1)..(word.len() + 1) {
+                self.substrings.insert(word[start..end].to_vec());
+            }
+        }
+        for len in 1..word.len() {
+            self.prefixes.entry(word[..len].to_vec()).or_default().push(idx);
+            self.suffixes.entry(word[word.len() - len..].to_vec()).or_default().push(idx);
+        }
+        self.set.insert(word.clone());
+        self.words.push(word);
+    }
+
+    /// Whether `word` contains, or is contained in, one of the words.
+    fn nested(&self, word: &[u8]) -> bool {
+        if self.substrings.contains(word) {
+            return true;
+        }
+        (0..word.len()).any(|start| ((start + 1)..(word.len() + 1)).any(|end| self.set.contains(&word[start..end])))
+    }
+
+    /// The most that the end of `word` overlaps the start of any of the words.
+    fn max_overlap_out(&self, word: &[u8]) -> usize {
+        (1..word.len()).rev().find(|&len| self.prefixes.contains_key(&word[word.len() - len..])).unwrap_or(0)
+    }
+}
+
+fn random_letters<R: Rng>(rng: &mut R, alphabet: u8, count: usize) -> Vec<u8> {
+    (0..count).map(|_| b'a' + rng.gen_range(0, alphabet)).collect()
+}
+
+fn first_word<R: Rng>(rng: &mut R, options: &Options) -> Vec<u8> {
+    let len = rng.gen_range(options.min_len, options.max_len + 1);
+    if options.starticle {
+        let mut word = b"portmanteau".to_vec();
+        // End it in the alphabet, so that it needs no joiners of its own.
+        let rest = ::std::cmp::max(1, len.saturating_sub(word.len()));
+        word.extend(random_letters(rng, options.alphabet, rest));
+        word
+    } else {
+        random_letters(rng, options.alphabet, len)
+    }
+}
+
+fn random_wordlist<R: Rng>(rng: &mut R, options: &Options) -> Result<Vec<Vec<u8>>, String> {
+    let mut words = Words::default();
+    if options.starticle {
+        words.push(first_word(rng, options));
+    }
+    let mut attempts = 0;
+    while words.words.len() < options.word_count {
+        attempts += 1;
+        if attempts > 1000 * options.word_count {
+            return Err(format!("could only make {} distinct words", words.words.len()));
+        }
+        let len = rng.gen_range(options.min_len, options.max_len + 1);
+        let mut word = Vec::new();
+        if !words.words.is_empty() && rng.gen_range(0.0, 1.0) < options.overlap {
+            let prev = &words.words[rng.gen_range(0, words.words.len())];
+            let max = ::std::cmp::min(options.max_overlap, ::std::cmp::min(prev.len(), len) - 1);
+            if max > 0 {
+                word.extend_from_slice(&prev[prev.len() - rng.gen_range(1, max + 1)..]);
+            }
+        }
+        let rest = len - word.len();
+        word.extend(random_letters(rng, options.alphabet, rest));
+        if !words.set.contains(&word) {
+            words.push(word);
+        }
+    }
+    Ok(words.words)
+}
+
+/// The chain of words and the overlap of each with the next.
+fn planted_wordlist<R: Rng>(rng: &mut R, options: &Options) -> Result<(Vec<Vec<u8>>, Vec<usize>), String> {
+    let mut words = Words::default();
+    let mut overlaps: Vec<usize> = Vec::new();
+    words.push(first_word(rng, options));
+    'next_word: while words.words.len() < options.word_count {
+        let last = words.words.len() - 1;
+        let is_last = words.words.len() + 1 == options.word_count;
+        for _ in 0..10000 {
+            let prev = &words.words[last];
+            let len = rng.gen_range(options.min_len, options.max_len + 1);
+            // The previous word must overlap this one at least as much as anything else.
+            let least = ::std::cmp::max(1, words.max_overlap_out(prev));
+            let most = ::std::cmp::min(options.max_overlap, ::std::cmp::min(prev.len(), len) - 1);
+            if least > most {
+                continue;
+            }
+            let overlap = rng.gen_range(least, most + 1);
+            let mut word = prev[prev.len() - overlap..].to_vec();
+            word.extend(random_letters(rng, options.alphabet, len - overlap));
+            if words.nested(&word) {
+                continue;
+            }
+
+            // No word may overlap the new one more than it overlaps its successor.
+            let too_much = (1..word.len()).any(|len| {
+                words.suffixes.get(&word[..len]).is_some_and(|idxs| idxs.iter().any(|&idx| {
+                    let planted = if idx == last { overlap } else { overlaps[idx] };
+                    len > planted
+                }))
+            });
+            if too_much {
+                continue;
+            }
+            let least_so_far = overlaps.iter().cloned().chain(Some(overlap)).min().unwrap();
+            if is_last && words.max_overlap_out(&word) > least_so_far {
+                continue;
+            }
+            // The new word must not overlap anything more than some successor can overlap it.
+            if words.max_overlap_out(&word) > options.max_overlap {
+                continue;
+            }
+
+            overlaps.push(overlap);
+            words.push(word);
+            continue 'next_word;
+        }
+        return Err(format!("could only plant {} words; try a bigger alphabet or longer words", words.words.len()));
+    }
+    Ok((words.words, overlaps))
+}
+
+fn main_result() -> ::std::result::Result<(), Box<dyn (::std::error::Error)>> {
+    use std::io::Write;
+
+    let mut options = Options {
+        word_count: 1000,
+        alphabet: 26,
+        min_len: 3,
+        max_len: 10,
+        overlap: 0.0,
+        max_overlap: 3,
+        starticle: false,
+    };
+    let mut seed = 0u64;
+    let mut planted = false;
+    let mut solution = None;
+    let mut arg_iter = ::std::env::args().skip(1);
+    while let Some(arg) = arg_iter.next() {
+        let mut value = |name: &str| arg_iter.next().ok_or(format!("{} requires a value", name));
+        match &arg[..] {
+            "--words" => options.word_count = value("--words")?.parse()?,
+            "--alphabet" => options.alphabet = value("--alphabet")?.parse()?,
+            "--min-len" => options.min_len = value("--min-len")?.parse()?,
+            "--max-len" => options.max_len = value("--max-len")?.parse()?,
+            "--overlap" => options.overlap = value("--overlap")?.parse()?,
+            "--max-overlap" => options.max_overlap = value("--max-overlap")?.parse()?,
+            "--seed" => seed = value("--seed")?.parse()?,
+            "--solution" => solution = Some(value("--solution")?),
+            "--starticle" => options.starticle = true,
+            "--planted" => planted = true,
+            _ => {
+                eprintln!("usage: make_wordlist [--words N] [--alphabet K] [--min-len N] [--max-len N] \
+                          [--overlap P] [--max-overlap N] [--starticle] [--seed N] [--planted [--solution FILE]]");
+                return Ok(());
+            }
+        }
+    }
+    if options.alphabet == 0 || options.alphabet > 26 || options.min_len < 2 || options.max_len < options.min_len {
+        return Err("need 1 to 26 letters, and words of at least two letters".into());
+    }
+
+    let seed = [seed as u32, (seed >> 32) as u32, 0x9e3779b9, 0x243f6a88];
+    let mut rng: rand::XorShiftRng = rand::SeedableRng::from_seed(seed);
+
+    let mut words = if planted {
+        let (words, overlaps) = planted_wordlist(&mut rng, &options)?;
+        let mut portmantout = words[0].clone();
+        for (word, &overlap) in words[1..].iter().zip(&overlaps) {
+            portmantout.extend_from_slice(&word[overlap..]);
+        }
+        eprintln!("planted portmantout: {} characters, which is optimal", portmantout.len());
+        if let Some(path) = solution {
+            let mut file = ::std::fs::File::create(&path)?;
+            file.write_all(&portmantout)?;
+            file.write_all(b"\n")?;
+        }
+        words
+    } else {
+        random_wordlist(&mut rng, &options)?
+    };
+    words.sort();
+
+    let stdout = ::std::io::stdout();
+    let mut out = ::std::io::BufWriter::new(stdout.lock());
+    for word in &words {
+        out.write_all(word)?;
+        out.write_all(b"\n")?;
+    }
+    eprintln!("{} words, {} characters", words.len(), words.iter().map(|w| w.len()).sum::<usize>());
+    Ok(())
+}
+
+pub fn main() {
+    match main_result() {
+        Ok(()) => {}
+        Err(e) => {
+            // The wordlist goes to stdout, so keep errors out of it.
+            eprintln!("error: {}", e);
+            ::std::process::exit(1);
+        }
+    }
+}
+
+#[test]
+fn test_planted_is_tight() {
+    let options = Options {
+        word_count: 40,
+        alphabet: 4,
+        min_len: 4,
+        max_len: 7,
+        overlap: 0.0,
+        max_overlap: 3,
+        starticle: false,
+    };
+    let seed = [1, 2, 3, 4];
+    let mut rng: rand::XorShiftRng = rand::SeedableRng::from_seed(seed);
+    let (words, overlaps) = planted_wordlist(&mut rng, &options).unwrap();
+
+    // Check the bound from scratch: the sum of each word's largest overlap with any other word,
+    // less the smallest of those, is what the chain gets.
+    let max_out: Vec<usize> = words.iter().enumerate().map(|(i, a)| {
+        words.iter().enumerate().filter(|&(j, _)| j != i).map(|(_, b)| {
+            (1..::std::cmp::min(a.len(), b.len())).rev().find(|&len| a.ends_with(&b[..len])).unwrap_or(0)
+        }).max().unwrap()
+    }).collect();
+    let bound = max_out.iter().sum::<usize>() - max_out.iter().min().unwrap();
+    assert_eq!(overlaps.iter().sum::<usize>(), bound);
+    for (i, a) in words.iter().enumerate() {
+        for b in &words[i + 1..] {
+            assert!(!carrycoat::contains_subsequence(a, b) && !carrycoat::contains_subsequence(b, a));
+        }
+    }
+}
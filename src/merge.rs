@@ -0,0 +1,322 @@
+//! Greedy merging of words into particles, always taking the biggest available overlap first.
+//!
+//! Rather than rescanning every word for the single biggest overlap after each merge, we
+//! bucket the candidate overlaps by length. A merged particle overlaps other particles
+//! exactly like its first word does on the left and its last word does on the right,
+//! so the buckets never gain new candidates; merging only makes some of them unavailable.
+
+use std::collections::BTreeMap;
+
+struct Word {
+    chars: Vec<u8>,
+
+    // (index of the following word, overlap length), if this word is not the end of its chain.
+    next: Option<(usize, usize)>,
+    has_prev: bool,
+
+    // Only meaningful at chain ends: the index of the first word in the chain.
+    chain_start: usize,
+
+    // Only meaningful at chain starts: the index of the last word in the chain.
+    chain_end: usize,
+}
+
+/// Words that end with, and words that start with, some particular string.
+#[derive(Default)]
+struct Group {
+    ends: Vec<usize>,
+    starts: Vec<usize>,
+}
+
+pub struct Merger {
+    words: Vec<Word>,
+
+    // buckets[k] holds the candidate overlaps of length k, grouped by the overlapping string.
+    buckets: Vec<BTreeMap<Vec<u8>, Group>>,
+
+    // If present, used to shuffle the candidates in each group before merging, and the arcs
+    // that `merge_level_matching` follows.
+    rng: Option<rand::XorShiftRng>,
+}
+
+impl Merger {
+    /// Merges can have overlaps of up to `max_overlap` characters.
+    pub fn new(word_list: Vec<Vec<u8>>, max_overlap: usize) -> Merger {
+        let mut buckets: Vec<BTreeMap<Vec<u8>, Group>> = (0..(max_overlap + 1)).map(|_| BTreeMap::new()).collect();
+        let mut words = Vec::new();
+        for (idx, chars) in word_list.into_iter().enumerate() {
+            for k in 1..::std::cmp::min(max_overlap + 1, chars.len()) {
+                buckets[k].entry(chars[chars.len() - k..].to_vec()).or_default().ends.push(idx);
+                buckets[k].entry(chars[..k].to_vec()).or_default().starts.push(idx);
+            }
+            words.push(Word { chars, next: None, has_prev: false, chain_start: idx, chain_end: idx });
+        }
+        Merger { words, buckets, rng: None }
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Merger {
+        let seed = [seed as u32, (seed >> 32) as u32, 0x9e3779b9, 0x243f6a88];
+        self.rng = Some(rand::SeedableRng::from_seed(seed));
+        self
+    }
+
+    /// Never merges anything in front of a word that starts with `prefix`, so that each such word
+    /// stays at the start of its particle.
+    pub fn keep_at_start(mut self, prefix: &[u8]) -> Merger {
+        let words = &self.words;
+        for groups in &mut self.buckets {
+            for group in groups.values_mut() {
+                group.starts.retain(|&idx| !words[idx].chars.starts_with(prefix));
+            }
+        }
+        self
+    }
+
+    /// Takes the candidates with overlap `k`, shuffled if we are breaking ties at random.
+    fn take_level(&mut self, k: usize) -> BTreeMap<Vec<u8>, Group> {
+        use rand::Rng;
+        let mut groups = ::std::mem::take(&mut self.buckets[k]);
+        if let Some(ref mut rng) = self.rng {
+            for group in groups.values_mut() {
+                rng.shuffle(&mut group.ends);
+                rng.shuffle(&mut group.starts);
+            }
+        }
+        groups
+    }
+
+    pub fn particle_count(&self) -> usize {
+        self.words.iter().filter(|w| !w.has_prev).count()
+    }
+
+    /// Appends the chain starting at `start_idx` to the chain ending at `end_idx`.
+    fn merge(&mut self, end_idx: usize, start_idx: usize, overlap: usize) {
+        let chain_start = self.words[end_idx].chain_start;
+        let chain_end = self.words[start_idx].chain_end;
+        assert!(chain_start != start_idx, "would have formed a cycle");
+        self.words[end_idx].next = Some((start_idx, overlap));
+        self.words[start_idx].has_prev = true;
+        self.words[chain_start].chain_end = chain_end;
+        self.words[chain_end].chain_start = chain_start;
+    }
+
+    /// Makes every available merge with overlap `k`. Returns the number of merges.
+    pub fn merge_level(&mut self, k: usize) -> usize {
+        let mut merges = 0;
+        let groups = self.take_level(k);
+        for group in groups.values() {
+            // Each word is in at most one group per level, so nothing outside of this group
+            // can take these starts away from us.
+            let mut starts: ::std::collections::VecDeque<usize> =
+                group.starts.iter().cloned().filter(|&idx| !self.words[idx].has_prev).collect();
+            for &end_idx in &group.ends {
+                if self.words[end_idx].next.is_some() {
+                    continue;
+                }
+                // Don't form a cycle!
+                let chain_start = self.words[end_idx].chain_start;
+                let position = starts.iter().position(|&start_idx| start_idx != chain_start);
+                if let Some(start_idx) = position.and_then(|position| starts.remove(position)) {
+                    self.merge(end_idx, start_idx, k);
+                    merges += 1;
+                }
+            }
+        }
+        merges
+    }
+
+    /// Like `merge_level`, but makes as many merges with overlap `k` as possible without
+    /// forming a cycle.
+    ///
+    /// Think of each group as a vertex, and of each chain as an arc from the group of its
+    /// start to the group of its end. Merges then join arcs into trails, and the most merges
+    /// come from covering the arcs with as few open trails as possible: one per unmatched
+    /// arc end at each vertex, or a single one for a component that is an Euler circuit.
+    pub fn merge_level_matching(&mut self, k: usize) -> usize {
+        let groups: Vec<Group> = self.take_level(k).into_values().collect();
+
+        // Arcs as (chain start, chain end, from vertex, to vertex). A chain that is too short to
+        // overlap on one side gets a vertex of its own there.
+        let mut sides: BTreeMap<usize, (Option<usize>, Option<usize>)> = BTreeMap::new();
+        for (group_idx, group) in groups.iter().enumerate() {
+            for &idx in group.starts.iter().filter(|&&idx| !self.words[idx].has_prev) {
+                sides.entry(idx).or_default().0 = Some(group_idx);
+            }
+            for &idx in group.ends.iter().filter(|&&idx| self.words[idx].next.is_none()) {
+                sides.entry(self.words[idx].chain_start).or_default().1 = Some(group_idx);
+            }
+        }
+        let mut vertex_count = groups.len();
+        let mut arcs = Vec::new();
+        for (&start_idx, &(from, to)) in &sides {
+            let mut vertex = |side: Option<usize>| side.unwrap_or_else(|| { vertex_count += 1; vertex_count - 1 });
+            let (from, to) = (vertex(from), vertex(to));
+            arcs.push((start_idx, self.words[start_idx].chain_end, from, to));
+        }
+
+        // Balance every vertex with dummy arcs to and from one extra vertex, so that the trails
+        // we want are the stretches of Euler circuits between dummy arcs.
+        let hub = vertex_count;
+        let mut balance = vec![0isize; hub + 1];
+        for &(_, _, from, to) in &arcs {
+            balance[from] += 1;
+            balance[to] -= 1;
+        }
+        let real_arcs = arcs.len();
+        for (vertex, &excess) in balance.iter().enumerate() {
+            for _ in 0..excess {
+                arcs.push((0, 0, hub, vertex));
+            }
+            for _ in excess..0 {
+                arcs.push((0, 0, vertex, hub));
+            }
+        }
+        let mut out_arcs = vec![Vec::new(); hub + 1];
+        for (arc_idx, &(_, _, from, _)) in arcs.iter().enumerate() {
+            out_arcs[from].push(arc_idx);
+        }
+        if let Some(ref mut rng) = self.rng {
+            // The circuits take the arcs out of each vertex in this order, so this decides
+            // which chains follow which.
+            use rand::Rng;
+            for vertex_arcs in &mut out_arcs {
+                rng.shuffle(vertex_arcs);
+            }
+        }
+
+        let mut merges = 0;
+        let mut used = vec![0; hub + 1];
+        for first in ::std::iter::once(hub).chain(0..hub) {
+            // Hierholzer's algorithm, which finds the circuit backwards.
+            let mut circuit = Vec::new();
+            let mut stack = vec![(first, None)];
+            while let Some(&(vertex, _)) = stack.last() {
+                if used[vertex] < out_arcs[vertex].len() {
+                    let arc_idx = out_arcs[vertex][used[vertex]];
+                    used[vertex] += 1;
+                    stack.push((arcs[arc_idx].3, Some(arc_idx)));
+                } else if let Some((_, Some(arc_idx))) = stack.pop() {
+                    circuit.push(arc_idx);
+                }
+            }
+            circuit.reverse();
+            // A circuit without dummy arcs is broken up between its last and first arcs.
+            for pair in circuit.windows(2) {
+                if pair[0] < real_arcs && pair[1] < real_arcs {
+                    self.merge(arcs[pair[0]].1, arcs[pair[1]].0, k);
+                    merges += 1;
+                }
+            }
+        }
+        merges
+    }
+
+    /// The particles, each as its list of (word index, overlap with the previous word).
+    pub fn chains(&self) -> Vec<Vec<(usize, usize)>> {
+        let mut result = Vec::new();
+        for (idx, word) in self.words.iter().enumerate() {
+            if word.has_prev {
+                continue;
+            }
+            let mut chain = vec![(idx, 0)];
+            let mut current = idx;
+            while let Some((next_idx, overlap)) = self.words[current].next {
+                chain.push((next_idx, overlap));
+                current = next_idx;
+            }
+            result.push(chain);
+        }
+        result
+    }
+
+    pub fn particle_chars(&self, chain: &[(usize, usize)]) -> Vec<u8> {
+        let mut chars = Vec::new();
+        for &(word_idx, overlap) in chain {
+            chars.extend_from_slice(&self.words[word_idx].chars[overlap..]);
+        }
+        chars
+    }
+
+    pub fn write_particles<P>(&self, path: P) -> ::std::io::Result<()>
+        where P: AsRef<::std::path::Path>
+    {
+        use std::io::Write;
+        let mut out = ::std::io::BufWriter::new(::std::fs::File::create(path)?);
+        for chain in self.chains() {
+            out.write_all(&self.particle_chars(&chain))?;
+            out.write_all(b"\n")?;
+        }
+        out.flush()
+    }
+
+    pub fn write_provenance<W>(&self, chain: &[(usize, usize)], out: &mut W) -> ::std::io::Result<()>
+        where W: ::std::io::Write
+    {
+        out.write_all(&self.particle_chars(chain))?;
+        let mut offset = 0;
+        for &(word_idx, overlap) in chain {
+            offset -= overlap;
+            let word = &self.words[word_idx].chars;
+            out.write_all(b"\t")?;
+            out.write_all(word)?;
+            write!(out, ":{}:{}", offset, overlap)?;
+            offset += word.len();
+        }
+        out.write_all(b"\n")
+    }
+}
+
+#[test]
+fn test_matching_beats_greedy() {
+    // Greedy merges "ba" with "ab" first, after which "ab" can only go back to "ba".
+    let words = vec![b"ab".to_vec(), b"ba".to_vec(), b"ac".to_vec()];
+    let mut merger = Merger::new(words.clone(), 16);
+    assert_eq!(merger.merge_level(1), 1);
+    let mut merger = Merger::new(words, 16);
+    assert_eq!(merger.merge_level_matching(1), 2);
+    let particles: Vec<Vec<u8>> = merger.chains().iter().map(|chain| merger.particle_chars(chain)).collect();
+    assert_eq!(particles, vec![b"abac".to_vec()]);
+}
+
+#[test]
+fn test_matching_avoids_cycles() {
+    let words = vec![b"ab".to_vec(), b"ba".to_vec(), b"bc".to_vec()];
+    let mut merger = Merger::new(words, 16);
+    assert_eq!(merger.merge_level_matching(1), 2);
+    let particles: Vec<Vec<u8>> = merger.chains().iter().map(|chain| merger.particle_chars(chain)).collect();
+    assert_eq!(particles, vec![b"babc".to_vec()]);
+}
+
+#[test]
+fn test_matching_breaks_ties_with_seed() {
+    // Any end can go with any start, and every seed makes all three merges.
+    let words: Vec<Vec<u8>> = ["xa", "za", "qa", "ay", "aw", "av"].iter().map(|w| w.as_bytes().to_vec()).collect();
+    let mut particle_sets = Vec::new();
+    for seed in 0..20 {
+        let mut merger = Merger::new(words.clone(), 16).with_seed(seed);
+        assert_eq!(merger.merge_level_matching(1), 3);
+        let mut particles: Vec<Vec<u8>> = merger.chains().iter().map(|chain| merger.particle_chars(chain)).collect();
+        particles.sort();
+        particle_sets.push(particles);
+    }
+    particle_sets.sort();
+    particle_sets.dedup();
+    assert!(particle_sets.len() > 1);
+}
+
+#[test]
+fn test_keep_at_start() {
+    // "freeport" would like to go in front of either "portmanteau" word.
+    let words: Vec<Vec<u8>> = ["freeport", "portmanteaus", "portmanteaux", "xylophone", "sport"].iter()
+        .map(|w| w.as_bytes().to_vec()).collect();
+    for seed in 0..20 {
+        for &matching in &[false, true] {
+            let mut merger = Merger::new(words.clone(), 16).with_seed(seed).keep_at_start(b"portmanteau");
+            for k in (1..17).rev() {
+                if matching { merger.merge_level_matching(k); } else { merger.merge_level(k); }
+            }
+            let particles: Vec<Vec<u8>> = merger.chains().iter().map(|chain| merger.particle_chars(chain)).collect();
+            assert_eq!(particles.iter().filter(|p| p.starts_with(b"portmanteau")).count(), 2);
+        }
+    }
+}
@@ -0,0 +1,378 @@
+//! Exports a particle set as a mixed-integer program, so that off-the-shelf MIP solvers can have
+//! a go at it, and imports their solutions.
+//!
+//! The model is the ATSP-path formulation: a binary variable `x_I_J` for each arc from particle
+//! I to particle J (numbered by line of the particle file), costing minus their overlap or plus
+//! the padding between them, and one in-arc and one out-arc per particle. A dummy node `d`
+//! stands for the two ends, and its only out-arc goes to the starticle. The costs are those of
+//! links that can actually be made (see `carrycoat::links`) from the joiners and the padding
+//! words no longer than `--padding-max-len` (default 10), so every solution is a portmantout.
+//!
+//! Without further constraints the model is only the assignment relaxation. `--mtz` adds the
+//! Miller-Tucker-Zemlin constraints, which rule out subtours, and `--two-cycle-cuts` rules out
+//! the cycles of two particles. `--keep K` keeps only the K cheapest out-arcs of each particle,
+//! which makes for a much smaller model that may no longer have a solution. Without it, every
+//! pair of particles gets an arc, and finding their costs takes time quadratic in the number
+//! of particles; with it, we only search for the cheapest links, which works for the full
+//! particle sets.
+//!
+//! The model is written in CPLEX LP format, or in free MPS format if the file name ends in
+//! ".mps". With `--import SOLUTION_FILE`, reads the values of the arc variables from a solution
+//! file (anything with lines of "... NAME VALUE ...", which covers the usual solvers) and
+//! writes the portmantout to `--output FILE` (by default out/mip-LENGTH.txt).
+
+extern crate carrycoat;
+
+use std::io::Write;
+
+use carrycoat::joiners;
+use carrycoat::links::{self, Dictionary, Targets};
+
+/// The name of node `idx`, where the dummy comes after the particles.
+fn node_name(idx: usize, particle_count: usize) -> String {
+    if idx == particle_count { "d".to_string() } else { idx.to_string() }
+}
+
+fn parse_node(name: &str, particle_count: usize) -> Option<usize> {
+    if name == "d" {
+        return Some(particle_count);
+    }
+    name.parse().ok().filter(|&idx| idx < particle_count)
+}
+
+fn arc_name(from: usize, to: usize, particle_count: usize) -> String {
+    format!("x_{}_{}", node_name(from, particle_count), node_name(to, particle_count))
+}
+
+/// The arc that a variable name stands for, if it does.
+fn parse_arc_name(name: &str, particle_count: usize) -> Option<(usize, usize)> {
+    let mut parts = name.strip_prefix("x_")?.split('_');
+    let from = parse_node(parts.next()?, particle_count)?;
+    let to = parse_node(parts.next()?, particle_count)?;
+    if parts.next().is_some() {
+        return None;
+    }
+    Some((from, to))
+}
+
+struct Model {
+    particle_count: usize,
+    total_len: usize,
+    // (from, to, cost), with the dummy as node `particle_count`.
+    arcs: Vec<(usize, usize, i64)>,
+    mtz: bool,
+    two_cycle_cuts: bool,
+}
+
+/// A linear constraint: terms, sense and right-hand side.
+struct Row {
+    name: String,
+    terms: Vec<(String, i64)>,
+    sense: &'static str,
+    rhs: i64,
+}
+
+impl Model {
+    fn rows(&self) -> Vec<Row> {
+        let n = self.particle_count;
+        let mut rows = Vec::new();
+        let mut out_terms = vec![Vec::new(); n + 1];
+        let mut in_terms = vec![Vec::new(); n + 1];
+        for &(from, to, _) in &self.arcs {
+            out_terms[from].push((arc_name(from, to, n), 1));
+            in_terms[to].push((arc_name(from, to, n), 1));
+        }
+        for (idx, terms) in out_terms.into_iter().enumerate() {
+            rows.push(Row { name: format!("out_{}", node_name(idx, n)), terms, sense: "=", rhs: 1 });
+        }
+        for (idx, terms) in in_terms.into_iter().enumerate() {
+            rows.push(Row { name: format!("in_{}", node_name(idx, n)), terms, sense: "=", rhs: 1 });
+        }
+        if self.two_cycle_cuts {
+            let arcs: ::std::collections::HashSet<(usize, usize)> =
+                self.arcs.iter().map(|&(from, to, _)| (from, to)).collect();
+            for &(from, to, _) in &self.arcs {
+                if from < to && to < n && arcs.contains(&(to, from)) {
+                    rows.push(Row {
+                        name: format!("two_{}_{}", from, to),
+                        terms: vec![(arc_name(from, to, n), 1), (arc_name(to, from, n), 1)],
+                        sense: "<=",
+                        rhs: 1,
+                    });
+                }
+            }
+        }
+        if self.mtz {
+            // u_I is the position of particle I in the path.
+            for &(from, to, _) in &self.arcs {
+                if from < n && to < n {
+                    rows.push(Row {
+                        name: format!("mtz_{}_{}", from, to),
+                        terms: vec![(format!("u_{}", from), 1), (format!("u_{}", to), -1),
+                                    (arc_name(from, to, n), n as i64)],
+                        sense: "<=",
+                        rhs: n as i64 - 1,
+                    });
+                }
+            }
+        }
+        rows
+    }
+
+    fn write_lp<W: Write>(&self, out: &mut W) -> ::std::io::Result<()> {
+        let n = self.particle_count;
+        writeln!(out, "\\ portmantout of {} particles; its length is {} plus the objective", n, self.total_len)?;
+        writeln!(out, "Minimize")?;
+        write!(out, " obj:")?;
+        for (count, &(from, to, cost)) in self.arcs.iter().enumerate() {
+            if count % 8 == 7 {
+                write!(out, "\n ")?;
+            }
+            write!(out, " {} {} {}", if cost < 0 { "-" } else { "+" }, cost.abs(), arc_name(from, to, n))?;
+        }
+        writeln!(out)?;
+        writeln!(out, "Subject To")?;
+        for row in self.rows() {
+            write!(out, " {}:", row.name)?;
+            for (count, (name, coefficient)) in row.terms.iter().enumerate() {
+                if count % 8 == 7 {
+                    write!(out, "\n ")?;
+                }
+                write!(out, " {} {} {}", if *coefficient < 0 { "-" } else { "+" }, coefficient.abs(), name)?;
+            }
+            writeln!(out, " {} {}", row.sense, row.rhs)?;
+        }
+        if self.mtz {
+            writeln!(out, "Bounds")?;
+            for idx in 0..n {
+                writeln!(out, " 1 <= u_{} <= {}", idx, n)?;
+            }
+        }
+        writeln!(out, "Binaries")?;
+        for &(from, to, _) in &self.arcs {
+            writeln!(out, " {}", arc_name(from, to, n))?;
+        }
+        writeln!(out, "End")
+    }
+
+    fn write_mps<W: Write>(&self, out: &mut W) -> ::std::io::Result<()> {
+        let n = self.particle_count;
+        let rows = self.rows();
+        let mut columns = ::std::collections::BTreeMap::<String, Vec<(String, i64)>>::new();
+        let mut arc_columns = Vec::new();
+        for &(from, to, cost) in &self.arcs {
+            let name = arc_name(from, to, n);
+            columns.insert(name.clone(), vec![("obj".to_string(), cost)]);
+            arc_columns.push(name);
+        }
+        for row in &rows {
+            for (name, coefficient) in &row.terms {
+                columns.entry(name.clone()).or_default().push((row.name.clone(), *coefficient));
+            }
+        }
+
+        writeln!(out, "* portmantout of {} particles; its length is {} plus the objective", n, self.total_len)?;
+        writeln!(out, "NAME portmantout")?;
+        writeln!(out, "ROWS")?;
+        writeln!(out, " N obj")?;
+        for row in &rows {
+            let sense = match row.sense { "=" => "E", "<=" => "L", _ => "G" };
+            writeln!(out, " {} {}", sense, row.name)?;
+        }
+        writeln!(out, "COLUMNS")?;
+        for (name, entries) in &columns {
+            for (row, coefficient) in entries {
+                writeln!(out, "    {} {} {}", name, row, coefficient)?;
+            }
+        }
+        writeln!(out, "RHS")?;
+        for row in &rows {
+            writeln!(out, "    rhs {} {}", row.name, row.rhs)?;
+        }
+        writeln!(out, "BOUNDS")?;
+        for name in &arc_columns {
+            writeln!(out, " BV bnd {}", name)?;
+        }
+        if self.mtz {
+            for idx in 0..n {
+                writeln!(out, " LO bnd u_{} 1", idx)?;
+                writeln!(out, " UP bnd u_{} {}", idx, n)?;
+            }
+        }
+        writeln!(out, "ENDATA")
+    }
+}
+
+/// The particles in the order that the chosen arcs visit them, starting after the dummy.
+fn path_from_arcs(chosen: &[(usize, usize)], particle_count: usize) -> ::std::result::Result<Vec<usize>, String> {
+    let mut next = vec![None; particle_count + 1];
+    for &(from, to) in chosen {
+        if next[from].is_some() {
+            return Err(format!("particle {} has more than one successor", node_name(from, particle_count)));
+        }
+        next[from] = Some(to);
+    }
+    let mut order = Vec::new();
+    let mut current = next[particle_count].ok_or("the dummy has no successor")?;
+    while current != particle_count {
+        if order.len() == particle_count {
+            return Err("the path from the dummy does not come back".to_string());
+        }
+        order.push(current);
+        current = next[current].ok_or_else(|| format!("particle {} has no successor", current))?;
+    }
+    if order.len() < particle_count {
+        return Err(format!("the path only visits {} of {} particles; the solution has subtours",
+                           order.len(), particle_count));
+    }
+    Ok(order)
+}
+
+#[test]
+fn test_path_from_arcs() {
+    assert_eq!(parse_arc_name("x_d_2", 3), Some((3, 2)));
+    assert_eq!(parse_arc_name(&arc_name(1, 3, 3), 3), Some((1, 3)));
+    assert_eq!(parse_arc_name("x_3_1", 3), None);
+    assert_eq!(path_from_arcs(&[(3, 2), (2, 0), (0, 1), (1, 3)], 3), Ok(vec![2, 0, 1]));
+    assert!(path_from_arcs(&[(3, 2), (2, 3), (0, 1), (1, 0)], 3).is_err());
+}
+
+fn main_result() -> ::std::result::Result<(), Box<dyn (::std::error::Error)>> {
+    use std::io::BufRead;
+
+    let mut mtz = false;
+    let mut two_cycle_cuts = false;
+    let mut keep = None;
+    let mut padding_max_len = joiners::DEFAULT_PADDING_MAX_LEN;
+    let mut import = None;
+    let mut output = None;
+    let mut args = Vec::new();
+    let mut arg_iter = ::std::env::args();
+    while let Some(arg) = arg_iter.next() {
+        match &arg[..] {
+            "--mtz" => mtz = true,
+            "--two-cycle-cuts" => two_cycle_cuts = true,
+            "--keep" => {
+                keep = Some(arg_iter.next().ok_or("--keep requires K")?.parse::<usize>()?);
+            }
+            "--padding-max-len" => {
+                padding_max_len = arg_iter.next().ok_or("--padding-max-len requires N")?.parse()?;
+            }
+            "--import" => {
+                import = Some(arg_iter.next().ok_or("--import requires SOLUTION_FILE")?);
+            }
+            "--output" => {
+                output = Some(arg_iter.next().ok_or("--output requires FILE")?);
+            }
+            _ => args.push(arg),
+        }
+    }
+    if args.len() != if import.is_some() { 4 } else { 5 } {
+        println!("usage: {} [--mtz] [--two-cycle-cuts] [--keep K] [--padding-max-len N] \
+                  PARTICLES_FILE JOINERS_FILE PADDING_WORDS_FILE MODEL_FILE", args[0]);
+        println!("       {} --import SOLUTION_FILE [--output FILE] [--padding-max-len N] \
+                  PARTICLES_FILE JOINERS_FILE PADDING_WORDS_FILE", args[0]);
+        return Ok(());
+    }
+
+    let mut particles = Vec::new();
+    for maybe_particle in ::std::io::BufReader::new(::std::fs::File::open(&args[1])?).split(b'\n') {
+        let particle = maybe_particle?;
+        if !particle.is_empty() {
+            particles.push(particle);
+        }
+    }
+    let starticle = particles.iter().position(|p| p.starts_with(b"portmanteau"))
+        .ok_or("no particle starts with 'portmanteau'")?;
+    let n = particles.len();
+
+    // The same vocabulary that join and search pad with.
+    let (_, padding_words) = joiners::load(&args[2], &args[3], padding_max_len)?;
+    let words = padding_words.words();
+    let dictionary = Dictionary::new(&words);
+
+    if let Some(solution_path) = import {
+        let mut chosen = Vec::new();
+        for line in ::std::io::BufReader::new(::std::fs::File::open(&solution_path)?).lines() {
+            let line = line?;
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            for pair in tokens.windows(2) {
+                if let Some(arc) = parse_arc_name(pair[0], n) {
+                    if pair[1].parse::<f64>().is_ok_and(|value| value > 0.5) {
+                        chosen.push(arc);
+                    }
+                    break;
+                }
+            }
+        }
+        let order = path_from_arcs(&chosen, n)?;
+        if order[0] != starticle {
+            return Err("the solution does not start with the starticle".into());
+        }
+        let mut order_links = Vec::new();
+        for pair in order.windows(2) {
+            let link = links::link(&particles[pair[0]], &particles[pair[1]], &dictionary).ok_or_else(|| {
+                format!("there is no way to get from particle {} to particle {}", pair[0], pair[1])
+            })?;
+            order_links.push(link);
+        }
+        let mut order_links = order_links.into_iter();
+        let portmantout = links::assemble(&particles, &order, |_, _| order_links.next().unwrap());
+        let output = output.unwrap_or_else(|| format!("out/mip-{}.txt", portmantout.len()));
+        let mut file = ::std::fs::File::create(&output)?;
+        file.write_all(&portmantout)?;
+        file.write_all(b"\n")?;
+        println!("length: {}", portmantout.len());
+        println!("wrote {}", output);
+        return Ok(());
+    }
+
+    let started = ::std::time::Instant::now();
+    let targets = Targets::new(&particles);
+    let mut arcs = Vec::new();
+    for from in 0..n {
+        let mut out_arcs: Vec<(usize, usize, i64)> = match keep {
+            // One more, in case the starticle is among them.
+            Some(keep) => links::cheapest_links_from(from, &particles, &targets, &dictionary, keep + 1).into_iter()
+                .filter(|&(to, _)| to != starticle)
+                .take(keep)
+                .map(|(to, link)| (from, to, link.cost()))
+                .collect(),
+            None => links::links_from(from, &particles, &targets, &dictionary).into_iter()
+                .enumerate()
+                .filter(|&(to, _)| to != starticle)
+                .filter_map(|(to, link)| link.map(|link| (from, to, link.cost())))
+                .collect(),
+        };
+        out_arcs.sort();
+        arcs.extend(out_arcs);
+        arcs.push((from, n, 0));
+    }
+    arcs.push((n, starticle, 0));
+    println!("particles: {}, arcs: {}, link costs took {:.1}s", n, arcs.len(), started.elapsed().as_secs_f64());
+
+    let model = Model {
+        particle_count: n,
+        total_len: particles.iter().map(|p| p.len()).sum(),
+        arcs,
+        mtz,
+        two_cycle_cuts,
+    };
+    let mut out = ::std::io::BufWriter::new(::std::fs::File::create(&args[4])?);
+    if args[4].ends_with(".mps") {
+        model.write_mps(&mut out)?;
+    } else {
+        model.write_lp(&mut out)?;
+    }
+    println!("wrote {}", args[4]);
+    Ok(())
+}
+
+pub fn main() {
+    match main_result() {
+        Ok(()) => {}
+        Err(e) => {
+            println!("error: {}", e);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Lower bounds on the cost of joining particles, in a compact form.
+//!
+//! Joining particle `i` to particle `j` costs minus their overlap, or else the length of the
+//! padding needed in between. There are far too many pairs to list, so we describe the costs
+//! with groups instead: each group connects a set of source particles to a set of target
+//! particles, with a cost that depends only on the target. The cheapest group connecting
+//! `i` to `j` gives the exact cost when they overlap and a lower bound otherwise.
+//!
+//! The costs come from a `CostModel`; with `cost::Length` they are the ones above.
+
+use std::collections::{HashMap, HashSet};
+
+use cost::CostModel;
+
+pub struct Group {
+    pub sources: Vec<usize>,
+    pub targets: Vec<(usize, i64)>,
+
+    // Length of the overlap, or zero for padding groups.
+    pub overlap: usize,
+}
+
+pub struct ParticleGraph {
+    pub particles: Vec<Vec<u8>>,
+
+    // Least padding that could ever follow / precede each particle.
+    pub min_padding_out: Vec<usize>,
+    pub min_padding_in: Vec<usize>,
+
+    pub groups: Vec<Group>,
+}
+
+impl ParticleGraph {
+    /// `words` is the vocabulary that paddings may be built from.
+    pub fn new(particles: Vec<Vec<u8>>, words: &[Vec<u8>], model: &dyn CostModel) -> ParticleGraph {
+        let min_padding_out = min_paddings(&particles, words);
+        let reverse = |strings: &[Vec<u8>]| -> Vec<Vec<u8>> {
+            strings.iter().map(|s| s.iter().rev().cloned().collect()).collect()
+        };
+        let min_padding_in = min_paddings(&reverse(&particles), &reverse(words));
+
+        let mut groups = Vec::new();
+
+        let mut suffixes = HashMap::<&[u8], Vec<usize>>::new();
+        for (idx, particle) in particles.iter().enumerate() {
+            for len in 1..particle.len() {
+                suffixes.entry(&particle[particle.len() - len..]).or_default().push(idx);
+            }
+        }
+        let mut prefixes = HashMap::<&[u8], Vec<usize>>::new();
+        for (idx, particle) in particles.iter().enumerate() {
+            for len in 1..particle.len() {
+                prefixes.entry(&particle[..len]).or_default().push(idx);
+            }
+        }
+        let mut overlaps: Vec<&[u8]> = suffixes.keys().cloned().filter(|s| prefixes.contains_key(s)).collect();
+        overlaps.sort();
+        for overlap in overlaps {
+            groups.push(Group {
+                sources: suffixes[overlap].clone(),
+                targets: prefixes[overlap].iter().map(|&idx| (idx, model.overlap(overlap.len()))).collect(),
+                overlap: overlap.len(),
+            });
+        }
+
+        let max_padding = min_padding_out.iter().cloned().max().unwrap_or(0);
+        for padding in 0..(max_padding + 1) {
+            let sources: Vec<usize> = (0..particles.len()).filter(|&idx| min_padding_out[idx] == padding).collect();
+            if sources.is_empty() {
+                continue;
+            }
+            let targets = (0..particles.len())
+                .map(|idx| (idx, model.min_padding(::std::cmp::max(padding, min_padding_in[idx]))))
+                .collect();
+            groups.push(Group { sources, targets, overlap: 0 });
+        }
+
+        ParticleGraph { particles, min_padding_out, min_padding_in, groups }
+    }
+
+    pub fn total_len(&self) -> usize {
+        self.particles.iter().map(|p| p.len()).sum()
+    }
+}
+
+/// For each particle, the least padding that could connect it to the start of any particle
+/// using a single word from `words`. If there is no way at all, we use the length of
+/// the longest word, which is still a lower bound on the (infinite) cost.
+pub fn min_paddings(particles: &[Vec<u8>], words: &[Vec<u8>]) -> Vec<usize> {
+    let mut sorted_words: Vec<&[u8]> = words.iter().map(|w| &w[..]).collect();
+    sorted_words.sort();
+    sorted_words.dedup();
+    let max_word_len = sorted_words.iter().map(|w| w.len()).max().unwrap_or(0);
+
+    let mut particle_prefixes = HashSet::<&[u8]>::new();
+    for particle in particles {
+        for len in 1..(::std::cmp::min(max_word_len, particle.len()) + 1) {
+            particle_prefixes.insert(&particle[..len]);
+        }
+    }
+
+    // Least padding after a word that starts with the given suffix of a particle.
+    let mut memo = HashMap::<&[u8], usize>::new();
+    let mut result = Vec::new();
+    for particle in particles {
+        let mut best = max_word_len;
+        for len in 1..(::std::cmp::min(max_word_len, particle.len()) + 1) {
+            let suffix = &particle[particle.len() - len..];
+            let padding = *memo.entry(suffix).or_insert_with(|| {
+                let mut best = usize::MAX;
+                let first = sorted_words.partition_point(|w| *w < suffix);
+                for word in sorted_words[first..].iter().take_while(|w| w.starts_with(suffix)) {
+                    for idx in (len..word.len()).take_while(|idx| idx - len < best) {
+                        if particle_prefixes.contains(&word[idx..]) {
+                            best = idx - len;
+                            break;
+                        }
+                    }
+                }
+                best
+            });
+            best = ::std::cmp::min(best, padding);
+        }
+        result.push(best);
+    }
+    result
+}
+
+#[test]
+fn test_min_paddings() {
+    let particles = vec![b"abc".to_vec(), b"xyz".to_vec()];
+    let words = vec![b"cqqx".to_vec(), b"zpa".to_vec(), b"bcx".to_vec()];
+    assert_eq!(min_paddings(&particles, &words), vec![0, 1]);
+}
+
+#[test]
+fn test_longest_overlap() {
+    // Particles can overlap by more than the words that they were merged from.
+    let shared = vec![b'q'; ::MAX_OVERLAP + 4];
+    let particles = vec![[&b"a"[..], &shared].concat(), [&shared[..], &b"b"[..]].concat()];
+    let graph = ParticleGraph::new(particles, &[], &::cost::Length);
+    assert!(graph.groups.iter().any(|group| group.overlap == shared.len() && group.sources == [0]));
+}
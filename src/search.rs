@@ -1,563 +1,705 @@
 extern crate radix_trie;
 extern crate rand;
 extern crate byteorder;
+extern crate carrycoat;
 
-use std::collections::{BinaryHeap, HashMap, HashSet, VecDeque};
+use std::collections::{BTreeSet, HashMap, VecDeque};
 
 use byteorder::{LittleEndian, ReadBytesExt};
+use carrycoat::chains::{self, BytesTrieKey, Edge, Edges, Next, NoNext, NoPrev, Particle, ParticleTrie, Prev,
+                        State};
+use carrycoat::cost::{self, CostModel};
+use carrycoat::joiners::{self, Joiners, PaddingWords};
 
-#[derive(PartialEq, Eq, Debug)]
-pub struct BytesTrieKey(Vec<u8>);
-
-impl ::radix_trie::TrieKey for BytesTrieKey {
-    fn encode(&self) -> Vec<u8> {
-        self.0.clone()
-    }
-}
-
-pub type Trie = ::radix_trie::Trie<BytesTrieKey, ()>;
-pub type ParticleTrie = ::radix_trie::Trie<BytesTrieKey, usize>;
-
-#[derive(Clone)]
-enum Edge {
-    Overlapped(usize), // overlapped with at least 1 letter.
-    Padded { padding: Vec<u8> }, // includes the zero padding case.
-}
-
-impl Edge {
-    fn score(&self) -> isize {
-        match *self {
-            Edge::Overlapped(n) => -(n as isize),
-            Edge::Padded { ref padding, .. } => padding.len() as isize,
+fn load_particles<P>(path: P, cyclic: bool) -> ::std::io::Result<State>
+    where P: AsRef<::std::path::Path>
+{
+    use std::io::{BufRead};
+    let mut result = State::new(cyclic);
+    let mut found_starticle = false;
+    for maybe_word in ::std::io::BufReader::new(try!(::std::fs::File::open(path))).split('\n' as u8) {
+        let word = try!(maybe_word);
+        if !found_starticle && (cyclic || word.starts_with("portmanteau".as_bytes())) {
+            found_starticle = true;
+            result.add_starticle(word);
+        } else {
+            result.add_particle(word);
         }
     }
-}
+    if !found_starticle {
+        return Err(::std::io::Error::new(::std::io::ErrorKind::InvalidData,
+                                         "no particle starts with 'portmanteau'"));
+    }
 
-#[derive(Clone)]
-struct Next {
-    next_idx: usize,
-    edge: Edge,
+    Ok(result)
 }
 
-#[derive(Clone, Debug)]
-struct NoNext {
-    chain_start_idx: usize,
-}
 
-#[derive(Clone)]
-struct Prev {
-    prev_idx: usize,
+/// What `resume` made of the portmantout it was given, to show how much the particle set has
+/// changed since.
+struct Resumed {
+    // Particles found in the portmantout, and occurrences that were not used.
+    found: usize,
+    skipped_occurrences: usize,
+    // Particles that were not found, and how many of them we could insert.
+    inserted: usize,
+    left_over: usize,
+    input_len: usize,
+    // Of the chain as found, and after the insertions.
+    seeded_score: i64,
+    score: i64,
 }
 
-#[derive(Clone)]
-struct NoPrev {
-    chain_end_idx: usize,
+/// Seeds the chains from the portmantout in the file at `path`.
+fn resume<P>(state: &mut State, path: P, edges: &WordEdges, model: &dyn CostModel) -> ::std::io::Result<Resumed>
+    where P: AsRef<::std::path::Path>
+{
+    use std::io::{Read};
+    let mut portmantout = Vec::new();
+    try!(try!(::std::fs::File::open(path)).read_to_end(&mut portmantout));
+    // Get rid of any trailing whitespace.
+    while portmantout.last().is_some_and(|&c| (c as char).is_whitespace()) {
+        portmantout.pop();
+    }
+    resume_from(state, &portmantout, edges, model)
 }
 
-#[derive(Clone)]
-struct Particle {
-    chars: Vec<u8>,
-    next: Result<Next, NoNext>,
-    prev: Result<Prev, NoPrev>,
-}
+/// Seeds the chains from an existing portmantout. Particles that do not occur in it
+/// (e.g. because the particle set has changed since) get inserted where they are cheapest,
+/// or are left unconnected for `coalesce` to deal with.
+fn resume_from(state: &mut State, portmantout: &[u8], edges: &WordEdges, model: &dyn CostModel)
+               -> ::std::io::Result<Resumed>
+{
+    let mut particle_indices = HashMap::<Vec<u8>, usize>::new();
+    let mut max_particle_len = 0;
+    for particle_idx in 0..state.particles.len() {
+        let particle = &state.particles[particle_idx];
+        max_particle_len = ::std::cmp::max(max_particle_len, particle.chars.len());
+        particle_indices.insert(particle.chars.clone(), particle_idx);
+    }
 
-impl Particle {
-    fn new(chars: Vec<u8>, idx: usize) -> Particle {
-        Particle {
-            chars: chars,
-            next: Err(NoNext {chain_start_idx: idx}),
-            prev: Err(NoPrev {chain_end_idx: idx}),
+    // Every occurrence of every particle, as (portmantout_idx, particle_idx).
+    let mut occurrences = Vec::new();
+    let mut deq = VecDeque::<Vec<u8>>::new();
+
+    for idx in 0..portmantout.len() {
+        let byte = portmantout[idx];
+        deq.push_back(Vec::new());
+        for word in deq.iter_mut() {
+            word.push(byte);
+            if let Some(&particle_idx) = particle_indices.get(word) {
+                occurrences.push((idx + 1 - word.len(), particle_idx));
+            }
+        }
+        if deq.len() > max_particle_len + 1 {
+            deq.pop_front();
         }
     }
-}
 
-#[derive(Clone)]
-struct State {
-    pub particles: Vec<Particle>,
-    score: isize,
-
-    // Set of indices of base particles unconnected on the right.
-    unconnected_on_right: Vec<usize>,
-
-   // Set of indices of base particles unconnected on the left.
-    unconnected_on_left: HashSet<usize>,
-
-    starticle_idx: usize,
-}
-
-impl State {
-    fn new() -> State {
-        State {
-            particles: Vec::new(),
-            score: 0,
-            unconnected_on_right: Vec::new(),
-            unconnected_on_left: HashSet::new(),
-            starticle_idx: 0,
+    // Walk the occurrences from left to right, keeping each particle's first occurrence
+    // that extends past the previously kept one. Otherwise the "overlap" with the previous
+    // particle could swallow the whole particle.
+    occurrences.sort_by_key(|&(portmantout_idx, particle_idx)| {
+        (portmantout_idx, portmantout_idx + state.particles[particle_idx].chars.len())
+    });
+    let mut placed = vec![false; state.particles.len()];
+    let mut chain: Vec<(usize, usize)> = Vec::new();
+    let mut skipped_occurrences = 0;
+    for (portmantout_idx, particle_idx) in occurrences {
+        let end = portmantout_idx + state.particles[particle_idx].chars.len();
+        let extends = match chain.last() {
+            None => true,
+            Some(&(prev_portmantout_idx, prev_particle_idx)) => {
+                portmantout_idx > prev_portmantout_idx &&
+                    end > prev_portmantout_idx + state.particles[prev_particle_idx].chars.len()
+            }
+        };
+        if placed[particle_idx] || !extends {
+            skipped_occurrences += 1;
+            continue;
         }
+        placed[particle_idx] = true;
+        chain.push((portmantout_idx, particle_idx));
     }
 
-    fn from_particle_file<P>(path: P) -> ::std::io::Result<State>
-        where P: AsRef<::std::path::Path>
-    {
-        use std::io::{BufRead};
-        let mut result = State::new();
-        let mut found_starticle = false;
-        for maybe_word in ::std::io::BufReader::new(try!(::std::fs::File::open(path))).split('\n' as u8) {
-            let word = try!(maybe_word);
-            if !found_starticle && word.starts_with("portmanteau".as_bytes()) {
-                found_starticle = true;
-                result.add_starticle(word);
-            } else {
-                result.add_particle(word);
+    if chain.is_empty() {
+        return Err(::std::io::Error::new(::std::io::ErrorKind::InvalidData,
+                                         "portmantout contains none of the particles"));
+    }
+
+    // Outside cyclic mode the portmantout has to start with the starticle, so it stays at the
+    // head of the chain: particles found before it are left for `insert_missing`, and if it
+    // was not found at all, it goes in front.
+    let mut starticle_edge = None;
+    if state.cyclic {
+        state.starticle_idx = chain[0].1;
+    } else {
+        let starticle_idx = state.starticle_idx;
+        match chain.iter().position(|&(_, particle_idx)| particle_idx == starticle_idx) {
+            Some(position) => {
+                for (_, particle_idx) in chain.drain(..position) {
+                    placed[particle_idx] = false;
+                    skipped_occurrences += 1;
+                }
+            }
+            None => {
+                let (starticle, head) = (&state.particles[starticle_idx], &state.particles[chain[0].1]);
+                let edge = edges.find_edge(starticle, head).ok_or_else(|| {
+                    ::std::io::Error::new(::std::io::ErrorKind::InvalidData,
+                                          "no edge from the starticle to the start of the portmantout")
+                })?;
+                placed[starticle_idx] = true;
+                starticle_edge = Some(edge);
             }
         }
+    }
 
-        Ok(result)
+    // Everything starts out unconnected. Link up the chain we found.
+    state.unconnected_on_left = (0..state.particles.len()).filter(|&idx| !placed[idx]).collect();
+    state.unconnected_on_right = state.unconnected_on_left.iter().cloned().collect();
+    let chain_end_idx = chain[chain.len() - 1].1;
+    state.unconnected_on_right.push(chain_end_idx);
+
+    for window in chain.windows(2) {
+        let (prev_portmantout_idx, prev_particle_idx) = window[0];
+        let (portmantout_idx, particle_idx) = window[1];
+        let prev_end = prev_portmantout_idx + state.particles[prev_particle_idx].chars.len();
+        let edge = if prev_end > portmantout_idx {
+            Edge::Overlapped(prev_end - portmantout_idx)
+        } else {
+            Edge::Padded { padding: portmantout[prev_end..portmantout_idx].to_vec() }
+        };
+        state.score += edge.cost(model, &state.particles[prev_particle_idx].chars, &state.particles[particle_idx].chars);
+        state.particles[prev_particle_idx].next = Ok(Next { next_idx: particle_idx, edge });
+        state.particles[particle_idx].prev = Ok(Prev { prev_idx: prev_particle_idx });
     }
+    if let Some(edge) = starticle_edge {
+        let (starticle_idx, head_idx) = (state.starticle_idx, chain[0].1);
+        state.score += edge.cost(model, &state.particles[starticle_idx].chars, &state.particles[head_idx].chars);
+        state.particles[starticle_idx].next = Ok(Next { next_idx: head_idx, edge });
+        state.particles[head_idx].prev = Ok(Prev { prev_idx: starticle_idx });
+    }
+    state.particles[state.starticle_idx].prev = Err(NoPrev { chain_end_idx });
+    state.particles[chain_end_idx].next = Err(NoNext { chain_start_idx: state.starticle_idx });
+
+    let seeded_score = state.score - state.unconnected_score();
+    let (inserted, left_over) = insert_missing(state, edges, model);
+    Ok(Resumed {
+        found: chain.len(),
+        skipped_occurrences,
+        inserted,
+        left_over,
+        input_len: portmantout.len(),
+        seeded_score,
+        score: state.score - state.unconnected_score(),
+    })
+}
 
-    fn resume<P>(&mut self, path: P) -> ::std::io::Result<()>
-        where P: AsRef<::std::path::Path>
-    {
-        use std::io::{Read};
-        let mut portmantout = Vec::new();
-        try!(try!(::std::fs::File::open(path)).read_to_end(&mut portmantout));
-        // Get rid of any trailing whitespace.
-        while (portmantout[portmantout.len() - 1] as char).is_whitespace() {
-            portmantout.pop();
-        }
+/// Tries to insert each unconnected singleton particle into the main chain, at the cheapest
+/// position we can find for it. We try the positions where it overlaps a neighbor, the end of
+/// the chain, and the few positions where padding it in with joiners looks cheapest.
+/// Returns (inserted, left unconnected).
+fn insert_missing(state: &mut State, edges: &WordEdges, model: &dyn CostModel) -> (usize, usize) {
+    // How many of the positions that look cheapest with joiners we price exactly.
+    const PADDED_CANDIDATES: usize = 4;
+
+    // Where to insert a particle: after `prev_idx`, and then either before the next particle
+    // (with the edge to it) or at the end of the chain (which starts at the given particle).
+    struct Insertion {
+        cost: i64,
+        prev_idx: usize,
+        edge_in: Edge,
+        next: Result<(usize, Edge), usize>,
+    }
 
+    fn index(by_suffix: &mut HashMap<Vec<u8>, Vec<usize>>, by_prefix: &mut HashMap<Vec<u8>, Vec<usize>>,
+             chars: &[u8], idx: usize) {
+        for len in 1..::std::cmp::min(4, chars.len()) {
+            by_suffix.entry(chars[chars.len() - len..].to_vec()).or_default().push(idx);
+            by_prefix.entry(chars[..len].to_vec()).or_default().push(idx);
+        }
+    }
 
-        // value: (particle_idx, Option<portmantout_idx>)
-        let mut particle_starts = HashMap::<Vec<u8>, (usize, Option<usize>)>::new();
+    // The edges of the chain, keyed by the letters on either side of them, as (cost, index of
+    // the particle before the edge). Padding a particle in costs the same for every edge with
+    // the same key as far as the joiners are concerned, so the most expensive edge is the one
+    // to replace.
+    type Gaps = HashMap<(u8, u8), BTreeSet<(i64, usize)>>;
+    fn gap(state: &State, prev_idx: usize, model: &dyn CostModel) -> Option<((u8, u8), (i64, usize))> {
+        let prev = &state.particles[prev_idx];
+        let next = prev.next.as_ref().ok()?;
+        let next_chars = &state.particles[next.next_idx].chars;
+        let key = (*prev.chars.last()?, *next_chars.first()?);
+        Some((key, (next.edge.cost(model, &prev.chars, next_chars), prev_idx)))
+    }
 
-        let mut max_particle_len = 0;
-        for particle_idx in 0..self.particles.len() {
-            let particle = &self.particles[particle_idx];
-            max_particle_len = ::std::cmp::max(max_particle_len, particle.chars.len());
-            particle_starts.insert(particle.chars.clone(), (particle_idx, None));
+    // Particles in the main chain, indexed by their short suffixes and prefixes.
+    let mut by_suffix = HashMap::<Vec<u8>, Vec<usize>>::new();
+    let mut by_prefix = HashMap::<Vec<u8>, Vec<usize>>::new();
+    let mut gaps = Gaps::new();
+    for idx in 0..state.particles.len() {
+        if !state.unconnected_on_left.contains(&idx) {
+            index(&mut by_suffix, &mut by_prefix, &state.particles[idx].chars, idx);
+            if let Some((key, gap)) = gap(state, idx, model) {
+                gaps.entry(key).or_default().insert(gap);
+            }
         }
+    }
 
-        let mut deq = VecDeque::<Vec<u8>>::new();
-
-        for idx in 0..portmantout.len() {
-            let byte = portmantout[idx];
-            deq.push_back(Vec::new());
-            for word in deq.iter_mut() {
-                word.push(byte);
-                match particle_starts.get_mut(word) {
-                    None => {}
-                    Some(&mut (_, ref mut b)) => {
-                        assert!(idx + 1 >= word.len());
-                        *b = Some(idx - word.len() + 1);
+    let mut missing: Vec<usize> = state.unconnected_on_left.iter().cloned().collect();
+    missing.sort();
+    let mut inserted = 0;
+    for missing_idx in missing {
+        let chars = state.particles[missing_idx].chars.clone();
+
+        // Candidate predecessors: particles we could overlap with on either side,
+        let mut candidates = Vec::new();
+        for len in 1..::std::cmp::min(4, chars.len()) {
+            if let Some(prevs) = by_suffix.get(&chars[..len]) {
+                candidates.extend(prevs.iter().cloned());
+            }
+            if let Some(nexts) = by_prefix.get(&chars[chars.len() - len..]) {
+                for &next_idx in nexts {
+                    if let Ok(ref prev) = state.particles[next_idx].prev {
+                        candidates.push(prev.prev_idx);
                     }
                 }
             }
-            if deq.len() > max_particle_len + 1 {
-                deq.pop_front();
-            }
-        }
-
-        // Ok, now push all of these things onto a binary heap.
-        struct BinaryHeapElement {
-            portmantout_idx: usize,
-            particle_idx: usize,
         }
-
-        impl PartialEq for BinaryHeapElement {
-            fn eq(&self, other: &BinaryHeapElement) -> bool {
-                return self.portmantout_idx == other.portmantout_idx;
+        // chain ends,
+        candidates.extend(state.unconnected_on_right.iter().cloned()
+                          .filter(|idx| !state.unconnected_on_left.contains(idx)));
+        // and the edges that we can pad around most cheaply with joiners.
+        if let (Some(&first), Some(&last)) = (chars.first(), chars.last()) {
+            let mut padded = Vec::new();
+            for (&(before, after), gaps) in &gaps {
+                let (joiner_in, joiner_out) = match (edges.joiners.padding(&[before], &[first]),
+                                                     edges.joiners.padding(&[last], &[after])) {
+                    (Some(joiner_in), Some(joiner_out)) => (joiner_in, joiner_out),
+                    _ => continue,
+                };
+                if let Some(&(edge_cost, prev_idx)) = gaps.iter().next_back() {
+                    let prev = &state.particles[prev_idx];
+                    let next = &state.particles[prev.next.as_ref().unwrap().next_idx];
+                    let cost = model.padding(&prev.chars, joiner_in, &chars) +
+                        model.padding(&chars, joiner_out, &next.chars) - edge_cost;
+                    padded.push((cost, prev_idx));
+                }
             }
+            padded.sort();
+            candidates.extend(padded.into_iter().take(PADDED_CANDIDATES).map(|(_, prev_idx)| prev_idx));
         }
-        impl Eq for BinaryHeapElement {}
-        impl ::std::cmp::Ord for BinaryHeapElement {
-            fn cmp(&self, other: &BinaryHeapElement) -> ::std::cmp::Ordering {
-                // reverse ordering
-                ::std::cmp::Ord::cmp(&other.portmantout_idx, &self.portmantout_idx)
-             }
-        }
-        impl ::std::cmp::PartialOrd for BinaryHeapElement {
-            fn partial_cmp(&self, other: &BinaryHeapElement) -> Option<::std::cmp::Ordering> {
-                Some(::std::cmp::Ord::cmp(self, other))
+        candidates.sort();
+        candidates.dedup();
+
+        let mut best: Option<Insertion> = None;
+        let missing = &state.particles[missing_idx];
+        for prev_idx in candidates {
+            let prev = &state.particles[prev_idx];
+            let edge_in = match edges.find_edge(prev, missing) {
+                Some(edge) => edge,
+                None => continue,
+            };
+            let mut cost = edge_in.cost(model, &prev.chars, &chars);
+            let next = match prev.next {
+                Ok(ref next) => {
+                    let next_chars = &state.particles[next.next_idx].chars;
+                    let edge_out = match edges.find_edge(missing, &state.particles[next.next_idx]) {
+                        Some(edge) => edge,
+                        None => continue,
+                    };
+                    cost += edge_out.cost(model, &chars, next_chars) - next.edge.cost(model, &prev.chars, next_chars);
+                    Ok((next.next_idx, edge_out))
+                }
+                Err(ref no_next) => Err(no_next.chain_start_idx),
+            };
+            if best.as_ref().is_none_or(|best| cost < best.cost) {
+                best = Some(Insertion { cost, prev_idx, edge_in, next });
             }
         }
 
-        let mut heap = BinaryHeap::<BinaryHeapElement>::new();
+        let Insertion { cost, prev_idx, edge_in, next } = match best {
+            Some(best) => best,
+            None => continue,
+        };
 
-        for (key, value) in particle_starts.iter() {
-            match value {
-                &(_, None) => panic!("did not find particle: {:?}", ::std::str::from_utf8(key)),
-                &(particle_idx, Some(portmantout_idx)) => {
-                    heap.push(BinaryHeapElement { portmantout_idx: portmantout_idx,
-                                                  particle_idx: particle_idx });
-                }
+        if let Some((key, gap)) = gap(state, prev_idx, model) {
+            gaps.get_mut(&key).unwrap().remove(&gap);
+        }
+        state.score += cost;
+        state.particles[prev_idx].next = Ok(Next { next_idx: missing_idx, edge: edge_in });
+        state.particles[missing_idx].prev = Ok(Prev { prev_idx });
+        match next {
+            Ok((next_idx, edge_out)) => {
+                state.particles[missing_idx].next = Ok(Next { next_idx, edge: edge_out });
+                state.particles[next_idx].prev = Ok(Prev { prev_idx: missing_idx });
+                state.unconnected_on_right.retain(|&idx| idx != missing_idx);
+            }
+            Err(chain_start_idx) => {
+                // We're appending to the end of the chain.
+                state.particles[missing_idx].next = Err(NoNext { chain_start_idx });
+                state.particles[chain_start_idx].prev = Err(NoPrev { chain_end_idx: missing_idx });
+                state.unconnected_on_right.retain(|&idx| idx != prev_idx);
             }
         }
-
-        let mut prev_indexes: Option<(usize, usize)> = None;
-
-        loop {
-            match heap.pop() {
-                Some( BinaryHeapElement{ portmantout_idx, particle_idx }) => {
-                    match prev_indexes {
-                        None => {
-                            self.starticle_idx = particle_idx;
-                        }
-                        Some((prev_portmantout_idx, prev_particle_idx)) => {
-                            let prev_len = self.particles[prev_particle_idx].chars.len();
-                            let edge = if prev_portmantout_idx + prev_len > portmantout_idx {
-                                Edge::Overlapped(prev_portmantout_idx + prev_len - portmantout_idx)
-                            } else {
-                                Edge::Padded {
-                                    padding: portmantout[(prev_portmantout_idx + prev_len)..portmantout_idx]
-                                        .to_vec()
-                                }
-                            };
-                            self.score += edge.score();
-
-                            self.particles[prev_particle_idx].next = Ok(Next {
-                                next_idx: particle_idx,
-                                edge: edge,
-                            });
-
-                            self.particles[particle_idx].prev = Ok(Prev {
-                                prev_idx: prev_particle_idx,
-                            });
-                        }
-                    }
-
-                    if heap.is_empty() {
-                        self.particles[self.starticle_idx].prev = Err(NoPrev {
-                            chain_end_idx: particle_idx,
-                        });
-                        self.particles[particle_idx].next = Err(NoNext {
-                            chain_start_idx: self.starticle_idx,
-                        });
-                        self.unconnected_on_right = vec![particle_idx];
-                        self.unconnected_on_left = HashSet::new();
-                    }
-
-                    prev_indexes = Some((portmantout_idx, particle_idx));
-
-                }
-                None => break,
+        state.unconnected_on_left.remove(&missing_idx);
+        for &idx in &[prev_idx, missing_idx] {
+            if let Some((key, gap)) = gap(state, idx, model) {
+                gaps.entry(key).or_default().insert(gap);
             }
         }
-
-        println!("resumed!");
-        Ok(())
-    }
-
-    fn add_starticle(&mut self, particle: Vec<u8>) {
-        let idx = self.particles.len();
-        let particle = Particle::new(particle, idx);
-        self.score += particle.chars.len() as isize;
-        self.particles.push(particle);
-        self.unconnected_on_right.push(idx);
-        self.starticle_idx = idx;
+        index(&mut by_suffix, &mut by_prefix, &chars, missing_idx);
+        inserted += 1;
     }
+    (inserted, state.unconnected_on_left.len())
+}
 
-    fn add_particle(&mut self, particle: Vec<u8>) {
-        let idx = self.particles.len();
-        let particle = Particle::new(particle, idx);
-        self.score += particle.chars.len() as isize;
-        self.particles.push(particle);
-        self.unconnected_on_right.push(idx);
-        self.unconnected_on_left.insert(idx);
+#[test]
+fn test_resume_changed_particles() {
+    let joiners = Joiners::new();
+    let padding_words = PaddingWords::new(joiners::DEFAULT_PADDING_MAX_LEN);
+    let edges = WordEdges { padding_words: &padding_words, joiners: &joiners };
+    let mut state = State::new(false);
+    state.add_starticle(b"portmanteau".to_vec());
+    for particle in &[&b"austere"[..], b"erection", b"ionic", b"qq"] {
+        state.add_particle(particle.to_vec());
     }
 
-    fn sanity_check(&self) {
-        assert_eq!(self.unconnected_on_right.len(), self.unconnected_on_left.len() + 1);
-    }
+    // "erection" and "qq" are new, and "zebra" is no longer a particle.
+    let resumed = resume_from(&mut state, b"portmanteausterezebraionic", &edges, &cost::Length).unwrap();
+    assert_eq!((resumed.found, resumed.inserted, resumed.left_over), (3, 1, 1));
+    assert_eq!((resumed.input_len, resumed.seeded_score), (26, 26));
+    // "erection" goes between "austere" and "ionic", overlapping both, instead of "zebra":
+    // "portmanteausterectionic".
+    assert_eq!(resumed.score, 23);
+    assert!(matches!(state.particles[1].next, Ok(Next { next_idx: 2, edge: Edge::Overlapped(3) })));
+    assert!(matches!(state.particles[2].next, Ok(Next { next_idx: 3, edge: Edge::Overlapped(3) })));
+    assert_eq!(state.unconnected_on_left.iter().collect::<Vec<_>>(), vec![&4]);
 }
 
-fn break_chains<R>(state: &mut State, rng: &mut R) where R: rand::Rng {
-    for particle_idx in 0..state.particles.len() {
-        state.sanity_check();
-        let maybe_next_idx = {
-            let particle = &mut state.particles[particle_idx];
-            if let Ok(ref next) = particle.next {
-                if rng.gen_range(0, 10000) < 3 {
-                    // We're going to break this up.
-                    state.score -= next.edge.score();
-                    Some(next.next_idx)
-                } else {
-                    None
-                }
-            } else {
-                None
-            }
-        };
+#[test]
+fn test_insert_missing_padded() {
+    let mut joiners = Joiners::new();
+    for joiner in &[&b"um"[..], b"nk", b"kaz"] {
+        joiners.insert(joiner.to_vec());
+    }
+    let padding_words = PaddingWords::new(joiners::DEFAULT_PADDING_MAX_LEN);
+    let edges = WordEdges { padding_words: &padding_words, joiners: &joiners };
+    let mut state = State::new(false);
+    state.add_starticle(b"portmanteau".to_vec());
+    for particle in &[&b"kayak"[..], b"moon", b"zoo"] {
+        state.add_particle(particle.to_vec());
+    }
 
+    // Neither "moon" nor "zoo" overlaps anything. "moon" replaces the "x" between
+    // "portmanteau" and "kayak", and "zoo" can only go at the end.
+    let resumed = resume_from(&mut state, b"portmanteauxkayak", &edges, &cost::Length).unwrap();
+    assert_eq!((resumed.found, resumed.inserted, resumed.left_over), (2, 2, 0));
+    assert_eq!((resumed.seeded_score, resumed.score), (17, 24));
+    assert_eq!(state.assemble().0, b"portmanteaumoonkayakazoo".to_vec());
+}
 
-        if let Some(next_idx) = maybe_next_idx {
+#[test]
+fn test_resume_keeps_starticle() {
+    let joiners = Joiners::new();
+    let padding_words = PaddingWords::new(joiners::DEFAULT_PADDING_MAX_LEN);
+    let edges = WordEdges { padding_words: &padding_words, joiners: &joiners };
+    let new_state = || {
+        let mut state = State::new(false);
+        state.add_starticle(b"portmanteauz".to_vec());
+        for particle in &[&b"zebra"[..], b"austere", b"ionic"] {
+            state.add_particle(particle.to_vec());
+        }
+        state
+    };
+
+    // The old portmantout started with a different starticle, so the new one goes in front.
+    let mut state = new_state();
+    let resumed = resume_from(&mut state, b"portmanteaxzebraustereionic", &edges, &cost::Length).unwrap();
+    assert_eq!((resumed.found, resumed.inserted, resumed.left_over), (3, 0, 0));
+    assert_eq!(state.starticle_idx, 0);
+    assert_eq!(state.assemble().0, b"portmanteauzebraustereionic".to_vec());
+
+    // Here "ionic" comes before the starticle, so it has to be inserted again.
+    let mut state = new_state();
+    let resumed = resume_from(&mut state, b"ionicportmanteauzebraustere", &edges, &cost::Length).unwrap();
+    assert_eq!((resumed.found, resumed.skipped_occurrences, resumed.left_over), (3, 1, 1));
+    assert_eq!(state.starticle_idx, 0);
+    assert!(matches!(state.particles[0].next, Ok(Next { next_idx: 1, edge: Edge::Overlapped(1) })));
+    assert_eq!(state.unconnected_on_left.iter().collect::<Vec<_>>(), vec![&3]);
+}
 
-            state.unconnected_on_right.push(particle_idx);
-            state.unconnected_on_left.insert(next_idx);
+/// Writes one JSON object per line describing the progress of the search.
+struct Metrics<W> {
+    out: W,
+    accepted: u64,
+    rejected: u64,
+    // The iteration of the last line written.
+    recorded: Option<u64>,
+}
 
-            // need to figure out the start and the end of the chain.
-            // so walk forward to the end.
-            let (chain_start_idx, chain_end_idx) = {
-                let mut current_idx = next_idx;
-                let chain_start_idx;
-                loop {
-                    let particle = &state.particles[current_idx];
-                    match particle.next {
-                        Ok(ref next) => {
-                            current_idx = next.next_idx;
-                        }
-                        Err(ref no_next) => {
-                            chain_start_idx = no_next.chain_start_idx;
-                            break;
-                        }
-                    }
-                }
-                (chain_start_idx, current_idx)
-            };
+impl Metrics<::std::io::BufWriter<::std::fs::File>> {
+    fn create(path: &str) -> ::std::io::Result<Metrics<::std::io::BufWriter<::std::fs::File>>> {
+        Ok(Metrics::new(::std::io::BufWriter::new(::std::fs::File::create(path)?)))
+    }
+}
 
-            {
-                let chain_start = &mut state.particles[chain_start_idx];
-                match chain_start.prev {
-                    Ok(_) => unreachable!(),
-                    Err(ref mut no_prev) => {
-                        no_prev.chain_end_idx = particle_idx;
-                    }
-                }
-            }
+impl<W: ::std::io::Write> Metrics<W> {
+    fn new(out: W) -> Metrics<W> {
+        Metrics { out, accepted: 0, rejected: 0, recorded: None }
+    }
 
-            {
-                let chain_end = &mut state.particles[chain_end_idx];
-                match chain_end.next {
-                    Ok(_) => unreachable!(),
-                    Err(ref mut no_next) => {
-                        no_next.chain_start_idx = next_idx;
-                    }
-                }
-            }
+    fn record(&mut self, iteration: u64, current_score: i64, best: &State) -> ::std::io::Result<()> {
+        let timestamp = ::std::time::SystemTime::now().duration_since(::std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs() as f64 + d.subsec_nanos() as f64 * 1e-9)
+            .unwrap_or(0.0);
+        let counts = best.edge_counts();
+        let average_padding = if counts.padded > 0 {
+            counts.padding_chars as f64 / counts.padded as f64
+        } else {
+            0.0
+        };
+        writeln!(self.out,
+                 "{{\"timestamp\":{:.3},\"iteration\":{},\"current_score\":{},\"best_score\":{},\
+                  \"accepted\":{},\"rejected\":{},\"overlap_edges\":{},\"padded_edges\":{},\
+                  \"average_padding\":{:.4}}}",
+                 timestamp, iteration, current_score, best.score, self.accepted, self.rejected,
+                 counts.overlapped, counts.padded, average_padding)?;
+        self.recorded = Some(iteration);
+        self.out.flush()
+    }
+}
 
+#[test]
+fn test_metrics() {
+    let mut state = State::new(false);
+    state.add_starticle(b"portmanteau".to_vec());
+    state.add_particle(b"austere".to_vec());
+    state.add_particle(b"ionic".to_vec());
+    state.particles[0].next = Ok(Next { next_idx: 1, edge: Edge::Overlapped(2) });
+    state.particles[1].next = Ok(Next { next_idx: 2, edge: Edge::Padded { padding: b"zebra".to_vec() } });
+
+    let mut metrics = Metrics::new(Vec::new());
+    metrics.accepted = 3;
+    metrics.rejected = 4;
+    metrics.record(7, 30, &state).unwrap();
+    assert_eq!(metrics.recorded, Some(7));
+    let line = String::from_utf8(metrics.out).unwrap();
+    assert!(line.starts_with("{\"timestamp\":"));
+    let rest = &line[line.find(',').unwrap()..];
+    assert_eq!(rest, ",\"iteration\":7,\"current_score\":30,\"best_score\":23,\"accepted\":3,\"rejected\":4,\
+                      \"overlap_edges\":1,\"padded_edges\":1,\"average_padding\":5.0000}\n");
+}
 
-            state.particles[next_idx].prev = Err(NoPrev {chain_end_idx: chain_end_idx});
-            state.particles[particle_idx].next = Err(NoNext {chain_start_idx: chain_start_idx});
+/// Conditions under which the main loop of `search` stops. By default it runs forever.
+/// The time limit counts from just before the first coalesce, and like the others it is only
+/// checked between coalesces, so it can be overrun by as long as one coalesce takes.
+#[derive(Default)]
+struct StopCriteria {
+    time_limit: Option<::std::time::Duration>,
+    max_iterations: Option<u64>,
+    target_score: Option<i64>,
+    patience: Option<u64>,
+}
 
+impl StopCriteria {
+    fn reason(&self, started: ::std::time::Instant, iteration: u64,
+              iterations_since_improvement: u64, score: i64) -> Option<&'static str> {
+        if self.target_score.is_some_and(|target| score <= target) {
+            Some("reached target score")
+        } else if self.max_iterations.is_some_and(|max| iteration >= max) {
+            Some("reached iteration limit")
+        } else if self.patience.is_some_and(|patience| iterations_since_improvement >= patience) {
+            Some("no improvement")
+        } else if self.time_limit.is_some_and(|limit| started.elapsed() >= limit) {
+            Some("reached time limit")
+        } else {
+            None
         }
     }
 }
 
+#[test]
+fn test_stop_criteria() {
+    let started = ::std::time::Instant::now();
+    assert_eq!(StopCriteria::default().reason(started, 1000, 1000, 0), None);
+
+    let stop = StopCriteria {
+        time_limit: Some(::std::time::Duration::from_secs(3600)),
+        max_iterations: Some(100),
+        target_score: Some(500),
+        patience: Some(10),
+    };
+    assert_eq!(stop.reason(started, 50, 5, 600), None);
+    assert_eq!(stop.reason(started, 50, 5, 500), Some("reached target score"));
+    assert_eq!(stop.reason(started, 100, 5, 600), Some("reached iteration limit"));
+    assert_eq!(stop.reason(started, 50, 10, 600), Some("no improvement"));
+
+    let stop = StopCriteria { time_limit: Some(::std::time::Duration::from_secs(0)), ..StopCriteria::default() };
+    assert_eq!(stop.reason(started, 0, 0, 600), Some("reached time limit"));
+}
 
 fn write_portmantout(state: &State) -> Result<(), ::std::io::Error> {
     use std::io::Write;
 
-    assert!(state.unconnected_on_left.len() == 0);
-    assert!(state.unconnected_on_right.len() == 1);
-    let filename = format!("out/{}.txt", state.score);
+    // Named by length, which is also the score unless there is a --cost model.
+    let chars = state.assemble().0;
+    let filename = if state.cyclic {
+        format!("out/cyclic-{}.txt", chars.len())
+    } else {
+        format!("out/{}.txt", chars.len())
+    };
     let mut file = try!(::std::fs::File::create(&filename));
-    let mut current_idx = state.starticle_idx;
-    let mut counter = 0;
-    loop {
-        counter += 1;
-        if counter > 150000 {
-            panic!("loopy!");
-        }
-        let particle = &state.particles[current_idx];
-
-        match particle.next {
-            Ok(ref next) => {
-                current_idx = next.next_idx;
-                match next.edge {
-                    Edge::Padded { ref padding, .. } => {
-                        try!(file.write_all(&particle.chars));
-                        try!(file.write_all(padding));
-                    }
-                    Edge::Overlapped(ref n) => {
-                        let n = *n;
-                        assert!(particle.chars.len() >= n);
-                        let write_len = particle.chars.len() - n;
-                        try!(file.write_all(&particle.chars[.. write_len]));
-                    }
-                }
-
-                //println!("overlap word: {:?}", ::std::str::from_utf8(&edge.overlap_word));
-            }
-            Err(_) => {
-                try!(file.write_all(&particle.chars));
-                break;
-            }
-        }
-    }
+    try!(file.write_all(&chars));
     Ok(())
 }
 
-fn find_next(_state: &State, words_trie: &Trie,
-             particle: &Particle, particles_trie: &ParticleTrie) -> Next {
-    // first try for an overlapped edge.
-    for start_idx in (particle.chars.len() - 3) .. particle.chars.len() {
-        let overlap = &particle.chars[start_idx..];
-        if let Some(node) = particles_trie.get_descendant(&BytesTrieKey(overlap.to_vec())) {
-            assert!(node.len() > 0);
-            let (_, &p_idx) = node.iter().next().expect("no value?");
-            return Next {
-                next_idx: p_idx,
-                edge: Edge::Overlapped(overlap.len()),
-            };
-        }
-    }
-
-    // okay, we can't get any overlap.
-
-    let mut best_padding: Option<Vec<u8>> = None;       // smaller is better
-    let mut best_next_particle_idx: Option<usize> = None; // particle that corresponded to the best padding.
-
-    let start_idx = particle.chars.len() - ::std::cmp::min(11, particle.chars.len());
+/// Edges between particles of English words: overlaps, or padding from a short word or joiner.
+struct WordEdges<'a> {
+    padding_words: &'a PaddingWords,
+    joiners: &'a Joiners,
+}
 
-    'find_best: for suffix_start in start_idx .. particle.chars.len() {
-        let suffix_len = particle.chars.len() - suffix_start;
-        let suffix = particle.chars[suffix_start ..].to_vec();
-        if let Some(node) = words_trie.get_descendant(&BytesTrieKey(suffix)) {
-            assert!(node.len() > 0);
-            // Cool. there is at least one word that starts with `suffix`.
+impl<'a> Edges for WordEdges<'a> {
+    /// Finds the best edge from `from` to the specific particle `to`. There is always one unless
+    /// the joiner table is missing the letter pair.
+    fn find_edge(&self, from: &Particle, to: &Particle) -> Option<Edge> {
+        let padding_words = self.padding_words;
+        let max_overlap = ::std::cmp::min(from.chars.len(), to.chars.len());
+        for overlap_len in (1..max_overlap).rev() {
+            if from.chars.ends_with(&to.chars[..overlap_len]) {
+                return Some(Edge::Overlapped(overlap_len));
+            }
+        }
 
-            for key in node.keys() {
-                let word = key.0.clone();
-                'added: for idx in suffix_len .. word.len() {
-                    let padding_len = idx - suffix_len;
-                    match best_padding {
-                        Some(ref p) if p.len() <= padding_len => {
-                            // We have no chance of doing better than our current best.
-                            break 'added;
+        let mut best_padding: Option<Vec<u8>> = None;
+        let start_idx = from.chars.len() - ::std::cmp::min(padding_words.longest, from.chars.len());
+        for suffix_start in start_idx .. from.chars.len() {
+            let suffix_len = from.chars.len() - suffix_start;
+            let suffix = from.chars[suffix_start ..].to_vec();
+            if let Some(node) = padding_words.trie.get_descendant(&BytesTrieKey(suffix)) {
+                for key in node.keys() {
+                    let word = &key.0;
+                    for idx in suffix_len .. word.len() {
+                        if let Some(ref p) = best_padding {
+                            if p.len() <= idx - suffix_len {
+                                break;
+                            }
                         }
-                        _ => {}
-                    }
-                    match particles_trie.get_descendant(&BytesTrieKey(word[idx..].to_vec())) {
-                        Some(particle_node) if particle_node.len() > 0 => {
-                            let (_, &p_idx) = particle_node.iter().next().expect("no value?");
+                        if to.chars.starts_with(&word[idx..]) {
                             best_padding = Some(word[suffix_len..idx].to_vec());
-                            best_next_particle_idx = Some(p_idx);
-                            if padding_len == 0 {
-                                // We're not going to do better than this.
-                                break 'find_best;
-                            }
                         }
-                        _ => {}
                     }
                 }
             }
         }
+        best_padding.or_else(|| self.joiners.padding(&from.chars, &to.chars).map(|padding| padding.to_vec()))
+            .map(|padding| Edge::Padded { padding })
     }
-    if let (Some(next_particle_idx), Some(padding)) = (best_next_particle_idx, best_padding) {
-        return Next {
-            next_idx: next_particle_idx,
-            edge: Edge::Padded { padding: padding },
-        }
-    } else {
-        unreachable!()
-    }
-}
-
-
-fn coalesce<R>(state: &mut State, words_trie: &Trie, rng: &mut R) where R: rand::Rng {
-    // first, form a trie containing all of the current chains of particles
-    // then, while the length of unconnected_on_right is greater than 1:
-    // pick a random idx in unconnected on right
-    // connect that particle to one of the existing chains (but not itself!)
-    //  (so temporarily remove self from the particle-chain trie?)
-    //
-    //
-
-    let mut particles_trie = ParticleTrie::new();
-    for &idx in &state.unconnected_on_left {
-        let particle = &state.particles[idx];
-        particles_trie.insert(BytesTrieKey(particle.chars.clone()), idx);
-    }
-    'outer: while particles_trie.len() > 0 {
 
-        state.sanity_check();
-
-        let idx = rng.gen_range(0, state.unconnected_on_right.len());
-        let particle_idx = state.unconnected_on_right.swap_remove(idx);
-
-        let chain_start_particle_idx = match state.particles[particle_idx].next {
-            Ok(_) => unreachable!(),
-            Err(ref no_next) => no_next.chain_start_idx,
-        };
-
-        // Special case when we are almost done. We need to choose the starticle chain.
-        if particles_trie.len() == 1 && chain_start_particle_idx != state.starticle_idx {
-            state.unconnected_on_right.push(particle_idx);
-            continue;
-        }
-
-
-        let best_next = {
-            let particle = &state.particles[particle_idx];
-            let chain_start_particle = &state.particles[chain_start_particle_idx];
-            let chain_start_particle_trie_key = BytesTrieKey(chain_start_particle.chars.clone());
-            // temporarily remove chain_start_particle from particles_trie, to avoid forming a cycle.
-            if chain_start_particle_idx != state.starticle_idx {
-                particles_trie.remove(&chain_start_particle_trie_key);
+    fn find_next(&self, particle: &Particle, particles_trie: &ParticleTrie, model: &dyn CostModel) -> Next {
+        let padding_words = self.padding_words;
+        // first try for an overlapped edge.
+        for start_idx in particle.chars.len().saturating_sub(3) .. particle.chars.len() {
+            let overlap = &particle.chars[start_idx..];
+            if let Some(node) = particles_trie.get_descendant(&BytesTrieKey(overlap.to_vec())) {
+                assert!(node.len() > 0);
+                let (_, &p_idx) = node.iter().next().expect("no value?");
+                return Next {
+                    next_idx: p_idx,
+                    edge: Edge::Overlapped(overlap.len()),
+                };
             }
+        }
 
-            let best_next = find_next(state, words_trie, particle, &particles_trie);
+        // okay, we can't get any overlap.
 
-            if chain_start_particle_idx != state.starticle_idx {
-                particles_trie.insert(chain_start_particle_trie_key, chain_start_particle_idx);
-            }
+        let mut best_padding: Option<(i64, Vec<u8>)> = None; // cheapest padding, and its cost.
+        let mut best_next_particle_idx: Option<usize> = None;  // particle that corresponded to the best padding.
 
-            best_next
-        };
+        let start_idx = particle.chars.len() - ::std::cmp::min(padding_words.longest, particle.chars.len());
 
-        state.score += best_next.edge.score();
-        let next_particle_idx = best_next.next_idx;
-        assert!(state.unconnected_on_left.remove(&next_particle_idx));
+        'find_best: for suffix_start in start_idx .. particle.chars.len() {
+            let suffix_len = particle.chars.len() - suffix_start;
+            let suffix = particle.chars[suffix_start ..].to_vec();
+            if let Some(node) = padding_words.trie.get_descendant(&BytesTrieKey(suffix)) {
+                assert!(node.len() > 0);
+                // Cool. there is at least one word that starts with `suffix`.
 
-        {
-            let particle = &mut state.particles[particle_idx];
-            particle.next = Ok(best_next);
-        }
-
-        let chain_end_particle_idx = {
-            let next_particle = &mut state.particles[next_particle_idx];
-            let chain_end_particle_idx = match next_particle.prev {
-                Ok(_) => unreachable!(),
-                Err(ref no_prev) => {
-                    no_prev.chain_end_idx
-                }
-            };
-            next_particle.prev = Ok(Prev { prev_idx: particle_idx, });
-            chain_end_particle_idx
-        };
-
-        // Now, update the chain_end_idx at the chain start,
-
-        {
-            let chain_start = &mut state.particles[chain_start_particle_idx];
-            match chain_start.prev {
-                Ok(_) => unreachable!(),
-                Err(ref mut no_prev) => {
-                    no_prev.chain_end_idx = chain_end_particle_idx;
+                for key in node.keys() {
+                    let word = key.0.clone();
+                    'added: for idx in suffix_len .. word.len() {
+                        let padding_len = idx - suffix_len;
+                        match best_padding {
+                            Some((best_cost, _)) if best_cost <= model.min_padding(padding_len) => {
+                                // We have no chance of doing better than our current best.
+                                break 'added;
+                            }
+                            _ => {}
+                        }
+                        match particles_trie.get_descendant(&BytesTrieKey(word[idx..].to_vec())) {
+                            Some(particle_node) if particle_node.len() > 0 => {
+                                let (key, &p_idx) = particle_node.iter().next().expect("no value?");
+                                let padding = &word[suffix_len..idx];
+                                let cost = model.padding(&particle.chars, padding, &key.0);
+                                if best_padding.as_ref().is_some_and(|&(best_cost, _)| best_cost <= cost) {
+                                    continue;
+                                }
+                                best_padding = Some((cost, padding.to_vec()));
+                                best_next_particle_idx = Some(p_idx);
+                                if cost <= model.min_padding(0) {
+                                    // We're not going to do better than this.
+                                    break 'find_best;
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
                 }
             }
         }
-
-        // and update the chain_start_idx at the chain end.
-        {
-            let chain_end = &mut state.particles[chain_end_particle_idx];
-            match chain_end.next {
-                Ok(_) => unreachable!(),
-                Err(ref mut no_next) => {
-                    no_next.chain_start_idx = chain_start_particle_idx;
-                }
+        if let (Some(next_particle_idx), Some((_, padding))) = (best_next_particle_idx, best_padding) {
+            return Next {
+                next_idx: next_particle_idx,
+                edge: Edge::Padded { padding: padding },
             }
         }
 
-        {
-            let next_particle = &state.particles[next_particle_idx];
-            particles_trie.remove(&BytesTrieKey(next_particle.chars.clone()));
-        }
-        if 0 == (state.unconnected_on_right.len() % 100) {
-            println!("left: {}. score: {}", state.unconnected_on_right.len(), state.score);
-        }
+        // No short word will do, so fall back on a joiner. main() checked that we have one for
+        // every pair of letters that could come up.
+        particles_trie.iter()
+            .filter_map(|(key, &next_idx)| {
+                self.joiners.padding(&particle.chars, &key.0).map(|padding| Next {
+                    next_idx,
+                    edge: Edge::Padded { padding: padding.to_vec() },
+                })
+            })
+            .next()
+            .expect("no joiner for any remaining particle")
     }
 }
 
+#[test]
+fn test_find_next_short_particles() {
+    let joiners = Joiners::new();
+    let mut padding_words = PaddingWords::new(joiners::DEFAULT_PADDING_MAX_LEN);
+    padding_words.insert(b"pax".to_vec());
+    let edges = WordEdges { padding_words: &padding_words, joiners: &joiners };
+    let mut particles_trie = ParticleTrie::new();
+    particles_trie.insert(BytesTrieKey(b"zp".to_vec()), 2);
+    particles_trie.insert(BytesTrieKey(b"xq".to_vec()), 3);
+
+    let next = edges.find_next(&Particle::new(b"qz".to_vec(), 1), &particles_trie, &cost::Length);
+    assert_eq!(next.next_idx, 2);
+    assert!(matches!(next.edge, Edge::Overlapped(1)));
+
+    // Nothing starts with "p", so "pax" pads "zp" to "xq".
+    particles_trie.remove(&BytesTrieKey(b"zp".to_vec()));
+    let next = edges.find_next(&Particle::new(b"zp".to_vec(), 2), &particles_trie, &cost::Length);
+    assert_eq!(next.next_idx, 3);
+    assert!(matches!(next.edge, Edge::Padded { ref padding } if padding == b"a"));
+}
+
 fn _accept_new_state<R>(e0: usize, e1: usize, temp: f64, rng: &mut R) -> bool
     where R: rand::Rng
 {
@@ -570,41 +712,79 @@ fn _accept_new_state<R>(e0: usize, e1: usize, temp: f64, rng: &mut R) -> bool
 }
 
 fn main_result() -> ::std::result::Result<(), Box<::std::error::Error>> {
-    use std::io::{BufRead};
-
-    let args: Vec<String> = ::std::env::args().collect();
+    use rand::Rng;
+
+    let mut cyclic = false;
+    let mut metrics = None;
+    let mut stop = StopCriteria::default();
+    let mut population_size = 1;
+    let mut padding_max_len = joiners::DEFAULT_PADDING_MAX_LEN;
+    let mut model: Box<dyn CostModel> = Box::new(cost::Length);
+    let mut args = Vec::new();
+    let mut arg_iter = ::std::env::args();
+    while let Some(arg) = arg_iter.next() {
+        match &arg[..] {
+            "--cyclic" => cyclic = true,
+            "--metrics" => {
+                let path = arg_iter.next().ok_or("--metrics requires a FILE")?;
+                metrics = Some(Metrics::create(&path)?);
+            }
+            "--time-limit" => {
+                let seconds = arg_iter.next().ok_or("--time-limit requires SECONDS")?.parse()?;
+                stop.time_limit = Some(::std::time::Duration::from_secs(seconds));
+            }
+            "--max-iterations" => {
+                stop.max_iterations = Some(arg_iter.next().ok_or("--max-iterations requires N")?.parse()?);
+            }
+            "--target-score" => {
+                stop.target_score = Some(arg_iter.next().ok_or("--target-score requires SCORE")?.parse()?);
+            }
+            "--population" => {
+                population_size = arg_iter.next().ok_or("--population requires N")?.parse()?;
+            }
+            "--padding-max-len" => {
+                padding_max_len = arg_iter.next().ok_or("--padding-max-len requires N")?.parse()?;
+            }
+            "--cost" => {
+                model = cost::parse(&arg_iter.next().ok_or("--cost requires MODEL")?)?;
+            }
+            "--patience" => {
+                stop.patience = Some(arg_iter.next().ok_or("--patience requires N")?.parse()?);
+            }
+            _ => args.push(arg),
+        }
+    }
     if args.len() < 4 || args.len() > 5 {
-        println!("usage: {} PARTICLES_FILE JOINERS_FILE WORDLIST_FILE [PORTMANTOUT_FILE]", args[0]);
+        println!("usage: {} [--cyclic] [--metrics FILE] [--time-limit SECONDS] [--max-iterations N] \
+                  [--target-score SCORE] [--patience N] [--population N] [--padding-max-len N] [--cost MODEL] \
+                  PARTICLES_FILE JOINERS_FILE PADDING_WORDS_FILE [PORTMANTOUT_FILE]",
+                 args[0]);
         return Ok(());
     }
 
-    let mut state = try!(State::from_particle_file(&args[1]));
-
-    if args.len() == 5 {
-        try!(state.resume(&args[4]));
-    }
-
-    println!("score: {}, starticle idx: {}", state.score, state.starticle_idx);
-
-    let mut words_trie = Trie::new();
+    let mut state = try!(load_particles(&args[1], cyclic));
 
-    let mut joiners = HashMap::<(u8, u8), Vec<u8>>::new();
-    for maybe_joiner in ::std::io::BufReader::new(try!(::std::fs::File::open(&args[2]))).split('\n' as u8) {
-        let joiner = try!(maybe_joiner);
-        words_trie.insert(BytesTrieKey(joiner.clone()), ());
-        let key = (*joiner.first().unwrap(), *joiner.last().unwrap());
-        if !joiners.contains_key(&key) {
-            joiners.insert(key, joiner);
-        }
+    let (joiners, padding_words) = joiners::load(&args[2], &args[3], padding_max_len)?;
+    // Outside cyclic mode, nothing is ever joined onto the front of the starticle.
+    let joined_onto = state.particles.iter().enumerate()
+        .filter(|&(idx, _)| state.cyclic || idx != state.starticle_idx)
+        .map(|(_, p)| &p.chars[..]);
+    let missing = joiners.missing_pairs_between(state.particles.iter().map(|p| &p.chars[..]), joined_onto);
+    if !missing.is_empty() {
+        return Err(format!("no joiner for letter pairs: {}", joiners::format_pairs(&missing)).into());
     }
+    println!("padding words: {} (longest {})", padding_words.len(), padding_words.longest);
+    let edges = WordEdges { padding_words: &padding_words, joiners: &joiners };
 
-    for maybe_word in ::std::io::BufReader::new(try!(::std::fs::File::open(&args[3]))).split('\n' as u8) {
-        let word = try!(maybe_word);
-        if word.len() < 11 { // (optimization)
-            words_trie.insert(BytesTrieKey(word), ());
-        }
+    if args.len() == 5 {
+        let resumed = resume(&mut state, &args[4], &edges, &*model)?;
+        println!("resumed! {} particles found, {} skipped occurrences, {} inserted, {} left unconnected",
+                 resumed.found, resumed.skipped_occurrences, resumed.inserted, resumed.left_over);
+        println!("input length {}, seeded chain score {}, after insertions {}",
+                 resumed.input_len, resumed.seeded_score, resumed.score);
     }
 
+    println!("score: {}, starticle idx: {}", state.score, state.starticle_idx);
 
     let mut urandom = try!(::std::fs::File::open("/dev/urandom"));
     let mut seed: [u32; 4] = [0; 4];
@@ -614,31 +794,119 @@ fn main_result() -> ::std::result::Result<(), Box<::std::error::Error>> {
     println!("seed {:?}", seed);
 
     let mut rng: rand::XorShiftRng = rand::SeedableRng::from_seed(seed);
+    let started = ::std::time::Instant::now();
 
-    coalesce(&mut state, &words_trie, &mut rng);
+    let initial_state = state.clone();
+    chains::coalesce(&mut state, &edges, &*model, &mut rng);
+    if state.cyclic {
+        state.close_cycle(&edges, &*model)?;
+    }
     try!(write_portmantout(&state));
 
-    let mut counter = 0;
+    // With --population, we keep several solutions around and combine them by crossover,
+    // rather than mutating a single solution with break_chains.
+    let mut population = Vec::new();
+    if population_size > 1 {
+        population.push(state.clone());
+        while population.len() < population_size {
+            if let Some(reason) = stop.reason(started, 0, 0, state.score) {
+                println!("stopping with {} population members: {}", population.len(), reason);
+                break;
+            }
+            let mut member = initial_state.clone();
+            if member.unconnected_on_left.is_empty() {
+                // We resumed from a complete portmantout, so perturb it instead.
+                chains::break_chains(&mut member, &*model, &mut rng);
+            }
+            chains::coalesce(&mut member, &edges, &*model, &mut rng);
+            if member.cyclic {
+                member.close_cycle(&edges, &*model)?;
+            }
+            println!("population member {} has score {}", population.len(), member.score);
+            if member.score < state.score {
+                state = member.clone();
+                write_portmantout(&state)?;
+            }
+            population.push(member);
+        }
+    }
+
+    if let Some(ref mut metrics) = metrics {
+        metrics.record(0, state.score, &state)?;
+    }
+
+    let mut iteration: u64 = 0;
+    let mut iterations_since_improvement: u64 = 0;
     loop {
-        counter += 1;
-        let mut new_state = state.clone();
-        break_chains(&mut new_state, &mut rng);
-        coalesce(&mut new_state, &words_trie, &mut rng);
+        if let Some(reason) = stop.reason(started, iteration, iterations_since_improvement, state.score) {
+            println!("stopping after {} iterations: {}", iteration, reason);
+            break;
+        }
+        iteration += 1;
+        let mut new_state = if population.is_empty() {
+            let mut new_state = state.clone();
+            chains::break_chains(&mut new_state, &*model, &mut rng);
+            new_state
+        } else {
+            let a = rng.gen_range(0, population.len());
+            let mut b = rng.gen_range(0, population.len() - 1);
+            if b >= a {
+                b += 1;
+            }
+            State::crossover(&population[a], &population[b], &*model)
+        };
+        chains::coalesce(&mut new_state, &edges, &*model, &mut rng);
+        if new_state.cyclic {
+            new_state.close_cycle(&edges, &*model)?;
+        }
+        let new_score = new_state.score;
+        let improved = new_score < state.score;
 
-        if new_state.score < state.score {
+        let accepted = if population.is_empty() {
+            improved
+        } else {
+            // Replace the worst member, unless the child looks like a duplicate.
+            let worst_idx = (0..population.len()).max_by_key(|&idx| population[idx].score).unwrap();
+            if new_score < population[worst_idx].score && population.iter().all(|p| p.score != new_score) {
+                population[worst_idx] = new_state.clone();
+                true
+            } else {
+                false
+            }
+        };
+
+        if let Some(ref mut metrics) = metrics {
+            if accepted {
+                metrics.accepted += 1;
+            } else {
+                metrics.rejected += 1;
+            }
+            if accepted || iteration.is_multiple_of(100) {
+                metrics.record(iteration, new_score, if improved { &new_state } else { &state })?;
+            }
+        }
+
+        if improved {
             state = new_state;
+            iterations_since_improvement = 0;
             println!("new best score: {}", state.score);
             try!(write_portmantout(&state));
         } else {
-            if counter > 100 {
-                use std::io::Write;
-                print!(".");
-                try!(::std::io::stdout().flush());
-                counter = 0;
-            }
+            iterations_since_improvement += 1;
         }
 
     }
+
+    if let Some(ref mut metrics) = metrics {
+        // Unless that is what the last line already says.
+        if metrics.recorded != Some(iteration) {
+            metrics.record(iteration, state.score, &state)?;
+        }
+    }
+    write_portmantout(&state)?;
+    println!("final score: {}", state.score);
+    println!("padded edges: {}, length: {}", state.edge_counts().padded, state.assemble().0.len());
+    Ok(())
 }
 
 pub fn main() {
@@ -0,0 +1,209 @@
+//! Short common superstrings of arbitrary byte strings, by the same steps that make a
+//! portmantout: drop the strings that other strings contain, merge the rest greedily by
+//! overlap (`merge`), then improve how the merged particles follow each other with the local
+//! search from `chains`. The result contains every string, but need not be the shortest that
+//! does.
+//!
+//! Where one string does not overlap the next, either they just abut (`Padding::Forbidden`,
+//! the classic problem), or the same given bytes go between them every time (`Padding::Free`,
+//! say a run of `N`s between the contigs of an assembly).
+
+use std::collections::HashMap;
+
+use chains::{self, BytesTrieKey, Edge, Edges, Next, Particle, ParticleTrie, State};
+use cost::{CostModel, Length};
+use merge::Merger;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Padding {
+    /// Nothing goes between strings that don't overlap.
+    Forbidden,
+    /// These bytes go between strings that don't overlap.
+    Free(Vec<u8>),
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+    pub padding: Padding,
+    /// Rounds of breaking and rejoining chains after the greedy merge.
+    pub iterations: u64,
+    pub seed: u64,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options { padding: Padding::Forbidden, iterations: 1000, seed: 0 }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Superstring {
+    pub chars: Vec<u8>,
+    /// Where each of the strings occurs in `chars`, in the order they were given.
+    pub offsets: Vec<usize>,
+}
+
+/// The strings that no other string contains (keeping the first of any duplicates), and for
+/// each string, which of those contains it and where.
+fn reduce(strings: &[Vec<u8>]) -> (Vec<usize>, Vec<(usize, usize)>) {
+    let mut kept: Vec<usize> = Vec::new();
+    let mut containers = vec![(0, 0); strings.len()];
+    let shortest = match strings.iter().map(|s| s.len()).filter(|&len| len > 0).min() {
+        Some(shortest) => shortest,
+        None => return (kept, containers),
+    };
+
+    // Longest first, so that whatever contains a string is kept before we get to it. Every
+    // string we look at is at least `shortest` long, so it contains its own first `shortest`
+    // bytes, and so does anything that contains it.
+    let mut by_len: Vec<usize> = (0..strings.len()).filter(|&idx| !strings[idx].is_empty()).collect();
+    by_len.sort_by_key(|&idx| ::std::cmp::Reverse(strings[idx].len()));
+    let mut grams: HashMap<&[u8], Vec<(usize, usize)>> = HashMap::new();
+    for idx in by_len {
+        let chars = &strings[idx][..];
+        let container = grams.get(&chars[..shortest]).and_then(|places| {
+            places.iter().cloned().find(|&(kept_idx, offset)| {
+                strings[kept[kept_idx]][offset..].starts_with(chars)
+            })
+        });
+        containers[idx] = match container {
+            Some(container) => container,
+            None => {
+                for offset in 0..(chars.len() + 1 - shortest) {
+                    grams.entry(&chars[offset..offset + shortest]).or_default().push((kept.len(), offset));
+                }
+                kept.push(idx);
+                (kept.len() - 1, 0)
+            }
+        };
+    }
+    (kept, containers)
+}
+
+/// Joins particles by their biggest overlap, or else by the padding.
+struct Joins {
+    padding: Vec<u8>,
+    longest: usize,
+}
+
+impl Joins {
+    fn padding_after(&self, particle: &Particle) -> Edge {
+        // The starticle is empty, and nothing needs to go in front of the first particle.
+        if particle.chars.is_empty() {
+            Edge::Padded { padding: Vec::new() }
+        } else {
+            Edge::Padded { padding: self.padding.clone() }
+        }
+    }
+}
+
+impl Edges for Joins {
+    fn find_next(&self, particle: &Particle, starts: &ParticleTrie, _model: &dyn CostModel) -> Next {
+        let most = ::std::cmp::min(self.longest, particle.chars.len().saturating_sub(1));
+        for overlap in (1..(most + 1)).rev() {
+            let suffix = particle.chars[particle.chars.len() - overlap..].to_vec();
+            if let Some(node) = starts.get_descendant(&BytesTrieKey(suffix)) {
+                if let Some((_, &next_idx)) = node.iter().next() {
+                    return Next { next_idx, edge: Edge::Overlapped(overlap) };
+                }
+            }
+        }
+        let (_, &next_idx) = starts.iter().next().expect("no particles to join");
+        Next { next_idx, edge: self.padding_after(particle) }
+    }
+
+    fn find_edge(&self, from: &Particle, to: &Particle) -> Option<Edge> {
+        let most = ::std::cmp::min(from.chars.len(), to.chars.len());
+        for overlap in (1..most).rev() {
+            if from.chars.ends_with(&to.chars[..overlap]) {
+                return Some(Edge::Overlapped(overlap));
+            }
+        }
+        Some(self.padding_after(from))
+    }
+}
+
+/// Finds a short string that contains each of `strings`.
+pub fn superstring(strings: &[Vec<u8>], options: &Options) -> Superstring {
+    let (kept, containers) = reduce(strings);
+    if kept.is_empty() {
+        return Superstring { chars: Vec::new(), offsets: vec![0; strings.len()] };
+    }
+
+    let longest = kept.iter().map(|&idx| strings[idx].len()).max().unwrap();
+    let mut merger = Merger::new(kept.iter().map(|&idx| strings[idx].clone()).collect(), longest);
+    for k in (1..(longest + 1)).rev() {
+        merger.merge_level(k);
+    }
+
+    // Where each kept string is: in which particle, and how far into it.
+    let chains = merger.chains();
+    let mut places = vec![(0, 0); kept.len()];
+    for (particle_idx, chain) in chains.iter().enumerate() {
+        let mut offset = 0;
+        for &(word_idx, overlap) in chain {
+            offset -= overlap;
+            places[word_idx] = (particle_idx, offset);
+            offset += strings[kept[word_idx]].len();
+        }
+    }
+
+    // An empty starticle leaves the search free to put any particle first.
+    let mut state = State::new(false);
+    state.add_starticle(Vec::new());
+    for chain in &chains {
+        state.add_particle(merger.particle_chars(chain));
+    }
+    let padding = match options.padding {
+        Padding::Forbidden => Vec::new(),
+        Padding::Free(ref padding) => padding.clone(),
+    };
+    let joins = Joins { padding, longest: state.particles.iter().map(|p| p.chars.len()).max().unwrap() };
+
+    let seed = [options.seed as u32, (options.seed >> 32) as u32, 0x9e3779b9, 0x243f6a88];
+    let mut rng: ::rand::XorShiftRng = ::rand::SeedableRng::from_seed(seed);
+    chains::coalesce(&mut state, &joins, &Length, &mut rng);
+    for _ in 0..options.iterations {
+        let mut new_state = state.clone();
+        chains::break_chains(&mut new_state, &Length, &mut rng);
+        chains::coalesce(&mut new_state, &joins, &Length, &mut rng);
+        if new_state.score < state.score {
+            state = new_state;
+        }
+    }
+
+    let (chars, positions) = state.assemble();
+    assert_eq!(chars.len() as i64, state.score);
+    let offsets = containers.iter().enumerate().map(|(idx, &(kept_idx, offset))| {
+        if strings[idx].is_empty() {
+            return 0;
+        }
+        let (particle_idx, word_offset) = places[kept_idx];
+        // The starticle is particle 0 of the state.
+        positions[particle_idx + 1] + word_offset + offset
+    }).collect();
+    Superstring { chars, offsets }
+}
+
+#[test]
+fn test_superstring() {
+    fn check(strings: &[&[u8]], options: &Options) -> Vec<u8> {
+        let strings: Vec<Vec<u8>> = strings.iter().map(|s| s.to_vec()).collect();
+        let result = superstring(&strings, options);
+        for (string, &offset) in strings.iter().zip(&result.offsets) {
+            assert!(result.chars[offset..].starts_with(string));
+        }
+        result.chars
+    }
+
+    let options = Options::default();
+    assert_eq!(check(&[b"cde", b"abc", b"bcd"], &options), b"abcde");
+    // Contained strings and duplicates cost nothing, wherever they are.
+    assert_eq!(check(&[b"bc", b"abcd", b"abcd", b"", b"cd"], &options), b"abcd");
+    assert_eq!(check(&[b"xy", b"ab"], &options).len(), 4);
+    assert_eq!(check(&[], &options), b"");
+
+    let options = Options { padding: Padding::Free(b"N".to_vec()), ..Options::default() };
+    let chars = check(&[b"ab", b"xy", b"by"], &options);
+    assert!(chars == b"abyNxy" || chars == b"xyNaby", "{:?}", chars);
+}
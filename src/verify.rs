@@ -125,12 +125,74 @@ fn verify_cover(portmantout: &[u8], words: &Trie) -> Result<(), usize> {
     return Ok(());
 }
 
+/// Like `verify_cover`, but treats `portmantout` as circular, so words may span the seam.
+fn verify_cyclic_cover(portmantout: &[u8], words: &Trie) -> Result<(), usize> {
+    let len = portmantout.len();
+
+    // longest_word[i] is the length of the longest word starting at index i.
+    let mut longest_word = vec![0; len];
+    for start_idx in 0..len {
+        let mut word = Vec::new();
+        for offset in 0..len {
+            word.push(portmantout[(start_idx + offset) % len]);
+            let key = BytesTrieKey(word.clone());
+            match words.get_descendant(&key) {
+                Some(node) if !node.is_empty() => {
+                    if words.get(&key).is_some() {
+                        longest_word[start_idx] = word.len();
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    // Go around twice, so that words spanning the seam get to cover the start. Every letter
+    // must be joined to the next one, seam included, by a word that spans both of them.
+    let mut covered_thru = 0;
+    for idx in 0..(2 * len) {
+        covered_thru = ::std::cmp::max(covered_thru, idx + longest_word[idx % len]);
+        if idx >= len && covered_thru <= idx + 1 {
+            return Err((idx + 1) % len);
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn test_verify_cyclic_cover() {
+    let mut words = Trie::new();
+    for word in &["abc", "cab"] {
+        words.insert(BytesTrieKey(word.as_bytes().to_vec()), ());
+    }
+    assert_eq!(verify_cyclic_cover(b"abc", &words), Ok(()));
+    assert_eq!(verify_cyclic_cover(b"bca", &words), Ok(()));
+    assert_eq!(verify_cyclic_cover(b"abcd", &words), Err(3));
+
+    // Words that only abut leave the text in pieces.
+    let mut words = Trie::new();
+    for word in &["ab", "cd"] {
+        words.insert(BytesTrieKey(word.as_bytes().to_vec()), ());
+    }
+    assert_eq!(verify_cover(b"abcd", &words), Err(2));
+    assert_eq!(verify_cyclic_cover(b"abcd", &words), Err(2));
+    assert_eq!(verify_cyclic_cover(b"cdab", &words), Err(2));
+}
+
 fn main_result() -> ::std::result::Result<(), Box<::std::error::Error>> {
     use std::io::{BufRead, Read};
 
-    let args : Vec<String> = ::std::env::args().collect();
+    let mut cyclic = false;
+    let mut args = Vec::new();
+    for arg in ::std::env::args() {
+        if arg == "--cyclic" {
+            cyclic = true;
+        } else {
+            args.push(arg);
+        }
+    }
     if args.len() != 4 {
-        println!("usage: {} PORTMANTOUT_FILE WORDLIST_FILE REDUCED_WORDLIST_FILE", args[0]);
+        println!("usage: {} [--cyclic] PORTMANTOUT_FILE WORDLIST_FILE REDUCED_WORDLIST_FILE", args[0]);
         return Ok(());
     }
 
@@ -151,7 +213,12 @@ fn main_result() -> ::std::result::Result<(), Box<::std::error::Error>> {
 
     println!("word count: {}", words.len());
 
-    match verify_cover(&portmantout, &words) {
+    let cover = if cyclic {
+        verify_cyclic_cover(&portmantout, &words)
+    } else {
+        verify_cover(&portmantout, &words)
+    };
+    match cover {
         Ok(()) => {
             println!("success! there is a cover.");
         }
@@ -166,7 +233,18 @@ fn main_result() -> ::std::result::Result<(), Box<::std::error::Error>> {
     }
     println!("reduced word count: {}", reduced_words.len());
 
-    match verify_contains_all(&portmantout, &reduced_words) {
+    let contained = if cyclic {
+        // Append enough of the start that words spanning the seam show up: all but the last
+        // letter of the longest word.
+        let longest = reduced_words.iter().map(|word| word.len()).max().unwrap_or(0);
+        let mut wrapped = portmantout.clone();
+        let wrap_len = ::std::cmp::min(longest.saturating_sub(1), portmantout.len());
+        wrapped.extend_from_slice(&portmantout[..wrap_len]);
+        verify_contains_all(&wrapped, &reduced_words)
+    } else {
+        verify_contains_all(&portmantout, &reduced_words)
+    };
+    match contained {
         Ok(()) => {
             println!("success! contains all words");
         }